@@ -0,0 +1,224 @@
+use std::time::{Duration, Instant};
+
+use futures_util::TryStreamExt;
+use serde_json::{Map, Value};
+use sqlx::mysql::MySqlColumn;
+use sqlx::{Column, Either, Executor, MySqlConnection, Row, TypeInfo};
+
+use crate::db::with_pinned;
+use crate::error::DbError;
+use crate::row::row_to_json;
+
+/// A column of a result set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ColumnMeta {
+    /// The column name or alias.
+    pub name: String,
+    /// The MySQL type name, e.g. `BIGINT` or `VARCHAR`.
+    pub type_name: String,
+}
+
+/// A note, warning or error reported by `SHOW WARNINGS`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SqlWarning {
+    /// `Note`, `Warning` or `Error`.
+    pub level: String,
+    /// The MySQL error number, e.g. `1365` for a division by zero.
+    pub code: u32,
+    /// The message.
+    pub message: String,
+}
+
+/// The outcome of [`run_admin_query`]: the rows along with what a SQL client would display.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct QueryExecution {
+    /// The columns of the result set, empty if the statement does not return rows.
+    pub columns: Vec<ColumnMeta>,
+    /// The rows, as converted by [`row_to_json`].
+    pub rows: Vec<Map<String, Value>>,
+    /// The number of rows inserted, updated or deleted.
+    pub rows_affected: u64,
+    /// The first auto-increment id generated by the statement, `0` if there is none.
+    pub last_insert_id: u64,
+    /// The warnings the statement raised.
+    pub warnings: Vec<SqlWarning>,
+    /// How long the statement took, excluding the warnings lookup.
+    pub elapsed: Duration,
+}
+
+/// Runs an arbitrary statement and returns its rows together with their metadata.
+///
+/// Meant for admin consoles that behave like a SQL client: `sql` is sent as is through the text
+/// protocol, so statements that cannot be prepared (`SHOW`, `EXPLAIN ANALYZE`, ...) work too.
+/// Rows are converted with [`row_to_json`], and the column names and types are taken from the
+/// first row. Afterwards, `SHOW WARNINGS` runs on the same connection to collect the warnings
+/// the statement raised. If no row was returned, the columns are looked up by preparing the
+/// statement instead, so that empty results still have headers; statements that cannot be
+/// prepared report no columns then.
+///
+/// Only use this with SQL from trusted operators; nothing in `sql` is validated.
+///
+/// # Example
+/// ```rust,no_run
+/// use zirv_db_sqlx::admin::run_admin_query;
+///
+/// # async fn example() -> Result<(), zirv_db_sqlx::error::DbError> {
+/// let execution = run_admin_query("SELECT id, name FROM users LIMIT 10").await?;
+/// println!(
+///     "{} rows in {:?}, {} warnings",
+///     execution.rows.len(),
+///     execution.elapsed,
+///     execution.warnings.len()
+/// );
+/// # Ok(())
+/// # }
+/// ```
+pub async fn run_admin_query(sql: &str) -> Result<QueryExecution, DbError> {
+    with_pinned(async |conn| execute(conn, sql).await).await
+}
+
+/// Runs `sql` on `conn` and collects its [`QueryExecution`].
+async fn execute(conn: &mut MySqlConnection, sql: &str) -> Result<QueryExecution, DbError> {
+    let mut execution = QueryExecution {
+        columns: Vec::new(),
+        rows: Vec::new(),
+        rows_affected: 0,
+        last_insert_id: 0,
+        warnings: Vec::new(),
+        elapsed: Duration::ZERO,
+    };
+
+    let started = Instant::now();
+    {
+        let mut results = (&mut *conn).fetch_many(sql);
+        while let Some(result) = results.try_next().await? {
+            match result {
+                Either::Left(done) => {
+                    execution.rows_affected += done.rows_affected();
+                    if execution.last_insert_id == 0 {
+                        execution.last_insert_id = done.last_insert_id();
+                    }
+                }
+                Either::Right(row) => {
+                    if execution.columns.is_empty() {
+                        execution.columns = row.columns().iter().map(column_meta).collect();
+                    }
+                    execution.rows.push(row_to_json(&row));
+                }
+            }
+        }
+    }
+    execution.elapsed = started.elapsed();
+    #[cfg(feature = "query-recorder")]
+    crate::recorder::record_values(
+        sql,
+        &[],
+        started,
+        execution.rows.len() as u64 + execution.rows_affected,
+    );
+
+    let warnings = conn.fetch_all("SHOW WARNINGS").await?;
+    execution.warnings = warnings
+        .iter()
+        .map(|row| {
+            let warning = row_to_json(row);
+            let text = |key: &str| match warning.get(key) {
+                Some(Value::String(s)) => s.clone(),
+                _ => String::new(),
+            };
+            SqlWarning {
+                level: text("Level"),
+                code: warning
+                    .get("Code")
+                    .and_then(Value::as_u64)
+                    .unwrap_or_default() as u32,
+                message: text("Message"),
+            }
+        })
+        .collect();
+
+    // After `SHOW WARNINGS`, since preparing a statement clears them.
+    if execution.rows.is_empty()
+        && let Ok(described) = conn.describe(sql).await
+    {
+        execution.columns = described.columns().iter().map(column_meta).collect();
+    }
+    Ok(execution)
+}
+
+/// Converts a column of a result set into its [`ColumnMeta`].
+fn column_meta(column: &MySqlColumn) -> ColumnMeta {
+    ColumnMeta {
+        name: column.name().to_owned(),
+        type_name: column.type_info().name().to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+    use serde_json::json;
+
+    #[test]
+    fn test_run_admin_query_reports_select_and_insert_metadata() {
+        test_util::run_with_db(|pool| async move {
+            sqlx::query("DROP TABLE IF EXISTS zirv_test_admin")
+                .execute(pool)
+                .await
+                .unwrap();
+            sqlx::query(
+                "CREATE TABLE zirv_test_admin \
+                 (id BIGINT UNSIGNED AUTO_INCREMENT PRIMARY KEY, name VARCHAR(16) UNIQUE)",
+            )
+            .execute(pool)
+            .await
+            .unwrap();
+
+            let select = run_admin_query("SELECT CAST(7 AS SIGNED) AS seven, 1 / 0 AS broken")
+                .await
+                .unwrap();
+            assert_eq!(select.columns[0].name, "seven");
+            assert_eq!(select.columns[0].type_name, "BIGINT");
+            assert_eq!(select.columns[1].name, "broken");
+            assert_eq!(select.rows.len(), 1);
+            assert_eq!(select.rows[0]["seven"], json!(7));
+            assert_eq!(select.rows[0]["broken"], Value::Null);
+            assert_eq!(select.rows_affected, 0);
+            assert_eq!(select.warnings.len(), 1);
+            assert_eq!(select.warnings[0].code, 1365);
+            assert_eq!(select.warnings[0].level, "Warning");
+
+            let empty = run_admin_query("SELECT id, name FROM zirv_test_admin")
+                .await
+                .unwrap();
+            assert!(empty.rows.is_empty());
+            let names: Vec<_> = empty.columns.iter().map(|c| c.name.as_str()).collect();
+            assert_eq!(names, ["id", "name"]);
+            assert_eq!(empty.columns[1].type_name, "VARCHAR");
+
+            let insert = run_admin_query("INSERT INTO zirv_test_admin (name) VALUES ('a'), ('b')")
+                .await
+                .unwrap();
+            assert!(insert.columns.is_empty());
+            assert!(insert.rows.is_empty());
+            assert_eq!(insert.rows_affected, 2);
+            assert_eq!(insert.last_insert_id, 1);
+            assert!(insert.warnings.is_empty());
+
+            let ignored = run_admin_query("INSERT IGNORE INTO zirv_test_admin (name) VALUES ('a')")
+                .await
+                .unwrap();
+            assert_eq!(ignored.rows_affected, 0);
+            assert_eq!(ignored.warnings[0].code, 1062);
+
+            sqlx::query("DROP TABLE zirv_test_admin")
+                .execute(pool)
+                .await
+                .unwrap();
+        });
+    }
+}
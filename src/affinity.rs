@@ -0,0 +1,321 @@
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use sqlx::MySqlConnection;
+use tokio::sync::Mutex;
+use zirv_config::read_config;
+
+use crate::db::{acquire, get_db_pool};
+use crate::idle_stack::Acquired;
+
+/// Default number of affinity slots.
+const DEFAULT_AFFINITY_SLOTS: usize = 2;
+
+static AFFINITY_SLOTS: OnceLock<AffinitySlots<Acquired>> = OnceLock::new();
+
+/// A connection kept in a slot.
+struct Kept<C> {
+    conn: C,
+    /// When the connection was taken from the pool.
+    kept_since: Instant,
+    /// When the last call on the connection finished.
+    idle_since: Instant,
+}
+
+/// How long a slot may keep its connection before handing it back to the pool.
+#[derive(Debug, Clone, Copy, Default)]
+struct SlotLimits {
+    /// Maximum time without a call (the pool's `idle_timeout`).
+    idle: Option<Duration>,
+    /// Maximum time in the slot (the pool's `max_lifetime`).
+    lifetime: Option<Duration>,
+}
+
+impl SlotLimits {
+    fn expired<C>(&self, kept: &Kept<C>, now: Instant) -> bool {
+        let over = |limit: Option<Duration>, since: Instant| {
+            limit.is_some_and(|limit| now.saturating_duration_since(since) >= limit)
+        };
+        over(self.idle, kept.idle_since) || over(self.lifetime, kept.kept_since)
+    }
+}
+
+/// A fixed set of slots, each keeping one connection for the keys mapped to it.
+struct AffinitySlots<C> {
+    slots: Vec<Mutex<Option<Kept<C>>>>,
+    limits: SlotLimits,
+}
+
+impl<C> AffinitySlots<C> {
+    fn new(count: usize, limits: SlotLimits) -> Self {
+        AffinitySlots {
+            slots: (0..count).map(|_| Mutex::new(None)).collect(),
+            limits,
+        }
+    }
+
+    /// Drops the connections of idle slots that have been kept for too long, so they go back to
+    /// the pool.
+    fn release_expired(&self, now: Instant) {
+        for slot in &self.slots {
+            if let Ok(mut slot) = slot.try_lock()
+                && slot
+                    .as_ref()
+                    .is_some_and(|kept| self.limits.expired(kept, now))
+            {
+                *slot = None;
+            }
+        }
+    }
+
+    /// Runs `f` on the connection of the slot `key` maps to, filling the slot with `acquire` if
+    /// it is empty. If the slot is busy (or there are no slots), `f` runs on a connection from
+    /// `acquire` that is not kept.
+    async fn run<A, F, T, E>(&self, key: u64, acquire: A, f: F) -> Result<T, E>
+    where
+        A: AsyncFnOnce() -> Result<C, sqlx::Error>,
+        F: AsyncFnOnce(&mut C) -> Result<T, E>,
+        E: From<sqlx::Error>,
+    {
+        self.release_expired(Instant::now());
+        let slot = match self.slots.len() {
+            0 => None,
+            len => self.slots[(key % len as u64) as usize].try_lock().ok(),
+        };
+        let Some(mut slot) = slot else {
+            let mut conn = acquire().await?;
+            return f(&mut conn).await;
+        };
+
+        let kept = match slot.as_mut() {
+            Some(kept) => kept,
+            None => {
+                let now = Instant::now();
+                slot.insert(Kept {
+                    conn: acquire().await?,
+                    kept_since: now,
+                    idle_since: now,
+                })
+            }
+        };
+        let result = f(&mut kept.conn).await;
+        kept.idle_since = Instant::now();
+        if result.is_err() {
+            // The error may have left the connection unusable; let the pool replace it.
+            *slot = None;
+        }
+        result
+    }
+}
+
+/// Runs `f` on a connection chosen by `key`, so repeated calls for the same key tend to reuse
+/// the same connection.
+///
+/// Keys are mapped to a fixed number of slots (`database.affinity_slots`, default 2, `0`
+/// disables affinity), each of which keeps its connection checked out between calls. Reusing a
+/// connection for the same entity keeps its prepared statements warm and lets a routing proxy
+/// send its queries to the same backend.
+///
+/// The affinity is advisory: if the slot is in use by a concurrent call, `f` runs on any
+/// connection from the pool instead of waiting, and a slot whose call fails starts over with a
+/// fresh connection. Session state set by `f` is seen by later calls for keys in the same slot.
+///
+/// # Pool size
+/// A connection kept in a slot counts towards `database.max_connections` but is not available
+/// to the rest of the pool, so every slot leaves one connection less for other callers. The
+/// number of slots is capped at half of `max_connections`. A slot hands its connection back to
+/// the pool once it has not been used for the pool's `idle_timeout`, or has been kept for the
+/// pool's `max_lifetime`; this is checked on every call of `with_affinity`, so in a process
+/// that stops calling it the slots keep their connections until the next call.
+///
+/// # Example
+/// ```rust,no_run
+/// use zirv_db_sqlx::affinity::with_affinity;
+///
+/// async fn load_balance(account_id: u64) -> Result<i64, sqlx::Error> {
+///     with_affinity(account_id, async |conn| {
+///         sqlx::query_scalar("SELECT balance FROM accounts WHERE id = ?")
+///             .bind(account_id)
+///             .fetch_one(conn)
+///             .await
+///     })
+///     .await
+/// }
+/// ```
+pub async fn with_affinity<F, T, E>(key: u64, f: F) -> Result<T, E>
+where
+    F: AsyncFnOnce(&mut MySqlConnection) -> Result<T, E>,
+    E: From<sqlx::Error>,
+{
+    let slots = AFFINITY_SLOTS.get_or_init(|| {
+        let options = get_db_pool().options();
+        let configured =
+            read_config!("database.affinity_slots", usize).unwrap_or(DEFAULT_AFFINITY_SLOTS);
+        AffinitySlots::new(
+            slot_count(configured, options.get_max_connections()),
+            SlotLimits {
+                idle: options.get_idle_timeout(),
+                lifetime: options.get_max_lifetime(),
+            },
+        )
+    });
+    slots
+        .run(
+            key,
+            async || acquire().await,
+            async |conn: &mut Acquired| f(conn).await,
+        )
+        .await
+}
+
+/// Caps the configured number of slots at half of the pool size.
+fn slot_count(configured: usize, max_connections: u32) -> usize {
+    configured.min(max_connections as usize / 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// Hands out connections identified by increasing numbers.
+    struct Connections(AtomicU32);
+
+    impl Connections {
+        async fn acquire(&self) -> Result<u32, sqlx::Error> {
+            Ok(self.0.fetch_add(1, Ordering::SeqCst) + 1)
+        }
+    }
+
+    async fn connection_for(
+        slots: &AffinitySlots<u32>,
+        connections: &Connections,
+        key: u64,
+    ) -> u32 {
+        slots
+            .run(
+                key,
+                async || connections.acquire().await,
+                async |conn: &mut u32| Ok::<_, sqlx::Error>(*conn),
+            )
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_same_key_reuses_the_connection() {
+        let slots = AffinitySlots::new(4, SlotLimits::default());
+        let connections = Connections(AtomicU32::new(0));
+
+        let first = connection_for(&slots, &connections, 42).await;
+        let other = connection_for(&slots, &connections, 43).await;
+        assert_ne!(first, other);
+        for _ in 0..5 {
+            assert_eq!(connection_for(&slots, &connections, 42).await, first);
+        }
+        // Keys mapping to the same slot share its connection.
+        assert_eq!(connection_for(&slots, &connections, 46).await, first);
+        assert_eq!(connections.0.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_busy_slot_falls_back_to_another_connection() {
+        let slots = AffinitySlots::new(1, SlotLimits::default());
+        let connections = Connections(AtomicU32::new(0));
+        let first = connection_for(&slots, &connections, 1).await;
+
+        let nested = slots
+            .run(
+                1,
+                async || connections.acquire().await,
+                async |_: &mut u32| {
+                    Ok::<_, sqlx::Error>(connection_for(&slots, &connections, 1).await)
+                },
+            )
+            .await
+            .unwrap();
+        assert_ne!(nested, first);
+
+        // The fallback connection is not kept.
+        assert_eq!(connection_for(&slots, &connections, 1).await, first);
+    }
+
+    #[tokio::test]
+    async fn test_failed_call_resets_the_slot() {
+        let slots = AffinitySlots::new(1, SlotLimits::default());
+        let connections = Connections(AtomicU32::new(0));
+        let first = connection_for(&slots, &connections, 7).await;
+
+        let result: Result<(), _> = slots
+            .run(
+                7,
+                async || connections.acquire().await,
+                async |_: &mut u32| Err(sqlx::Error::RowNotFound),
+            )
+            .await;
+        assert!(result.is_err());
+        assert_ne!(connection_for(&slots, &connections, 7).await, first);
+    }
+
+    #[tokio::test]
+    async fn test_idle_or_old_connections_go_back_to_the_pool() {
+        let connections = Connections(AtomicU32::new(0));
+        let idle = AffinitySlots::new(
+            2,
+            SlotLimits {
+                idle: Some(Duration::from_millis(20)),
+                lifetime: None,
+            },
+        );
+        let first = connection_for(&idle, &connections, 0).await;
+        assert_eq!(connection_for(&idle, &connections, 0).await, first);
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        // A call for another slot releases the idle one as well.
+        connection_for(&idle, &connections, 1).await;
+        assert!(idle.slots[0].try_lock().unwrap().is_none());
+        assert_ne!(connection_for(&idle, &connections, 0).await, first);
+
+        let old = AffinitySlots::new(
+            1,
+            SlotLimits {
+                idle: None,
+                lifetime: Some(Duration::from_millis(40)),
+            },
+        );
+        let first = connection_for(&old, &connections, 0).await;
+        for _ in 0..4 {
+            tokio::time::sleep(Duration::from_millis(15)).await;
+            connection_for(&old, &connections, 0).await;
+        }
+        assert_ne!(connection_for(&old, &connections, 0).await, first);
+    }
+
+    #[test]
+    fn test_slots_are_capped_at_half_the_pool() {
+        assert_eq!(slot_count(DEFAULT_AFFINITY_SLOTS, 10), 2);
+        assert_eq!(slot_count(8, 10), 5);
+        assert_eq!(slot_count(2, 1), 0);
+        assert_eq!(slot_count(0, 10), 0);
+    }
+
+    #[test]
+    fn test_with_affinity_reuses_the_database_connection() {
+        test_util::run_with_db(|_| async {
+            let connection_id = async |key| {
+                with_affinity(key, async |conn| {
+                    sqlx::query_scalar::<_, u64>("SELECT CONNECTION_ID()")
+                        .fetch_one(conn)
+                        .await
+                })
+                .await
+                .unwrap()
+            };
+
+            let first = connection_id(1234).await;
+            for _ in 0..3 {
+                assert_eq!(connection_id(1234).await, first);
+            }
+        });
+    }
+}
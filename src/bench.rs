@@ -0,0 +1,186 @@
+use std::time::{Duration, Instant};
+
+use crate::db::get_db_pool;
+
+/// Latency statistics of one query variant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimingStats {
+    /// Number of timed runs.
+    pub runs: usize,
+    /// Fastest run.
+    pub min: Duration,
+    /// Median run.
+    pub median: Duration,
+    /// 95th percentile (nearest rank).
+    pub p95: Duration,
+}
+
+impl TimingStats {
+    /// Computes the statistics of `samples`.
+    ///
+    /// # Panics
+    /// Panics if `samples` is empty.
+    pub fn from_samples(mut samples: Vec<Duration>) -> Self {
+        assert!(!samples.is_empty(), "no timing samples");
+        samples.sort_unstable();
+        let rank = |percentile: usize| (samples.len() * percentile).div_ceil(100).max(1) - 1;
+        TimingStats {
+            runs: samples.len(),
+            min: samples[0],
+            median: samples[rank(50)],
+            p95: samples[rank(95)],
+        }
+    }
+}
+
+/// The result of [`compare_queries`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryComparison {
+    /// Statistics of the first variant.
+    pub a: TimingStats,
+    /// Statistics of the second variant.
+    pub b: TimingStats,
+}
+
+impl QueryComparison {
+    /// Returns how much faster `b` is than `a` by median, e.g. `2.0` if it takes half the time.
+    pub fn median_speedup(&self) -> f64 {
+        self.a.median.as_secs_f64() / self.b.median.as_secs_f64()
+    }
+}
+
+/// Times two variants of a query against the global pool (requires the `testing` feature).
+///
+/// Each variant runs `runs` times and its rows are fetched completely. Runs alternate between
+/// the variants, so that warming the buffer pool or the statement cache benefits both equally.
+/// This is a development tool for comparing query plans on real data; it puts load on the
+/// database and should not be used on a production path.
+///
+/// # Errors
+/// Returns the first error raised by either variant.
+///
+/// # Panics
+/// Panics if `runs` is `0`.
+///
+/// # Example
+/// ```rust,no_run
+/// use zirv_db_sqlx::bench::compare_queries;
+///
+/// # async fn example() -> Result<(), sqlx::Error> {
+/// let comparison = compare_queries(
+///     "SELECT * FROM orders WHERE YEAR(created_at) = 2024",
+///     "SELECT * FROM orders WHERE created_at >= '2024-01-01' AND created_at < '2025-01-01'",
+///     50,
+/// )
+/// .await?;
+/// println!("{:?} ({:.1}x faster)", comparison, comparison.median_speedup());
+/// # Ok(())
+/// # }
+/// ```
+pub async fn compare_queries(
+    a: &str,
+    b: &str,
+    runs: usize,
+) -> Result<QueryComparison, sqlx::Error> {
+    compare_with(a, b, runs, async |sql| {
+        sqlx::query(sql).fetch_all(get_db_pool()).await.map(drop)
+    })
+    .await
+}
+
+/// Times `run` on both variants, alternating between them.
+async fn compare_with<F>(
+    a: &str,
+    b: &str,
+    runs: usize,
+    mut run: F,
+) -> Result<QueryComparison, sqlx::Error>
+where
+    F: AsyncFnMut(&str) -> Result<(), sqlx::Error>,
+{
+    assert!(runs > 0, "compare_queries needs at least one run");
+    let mut samples_a = Vec::with_capacity(runs);
+    let mut samples_b = Vec::with_capacity(runs);
+
+    for i in 0..runs {
+        // Swap the order every iteration so neither variant always runs first.
+        let order = if i % 2 == 0 {
+            [(a, &mut samples_a), (b, &mut samples_b)]
+        } else {
+            [(b, &mut samples_b), (a, &mut samples_a)]
+        };
+        for (sql, samples) in order {
+            let start = Instant::now();
+            run(sql).await?;
+            samples.push(start.elapsed());
+        }
+    }
+
+    Ok(QueryComparison {
+        a: TimingStats::from_samples(samples_a),
+        b: TimingStats::from_samples(samples_b),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    #[test]
+    fn test_timing_stats() {
+        let stats = TimingStats::from_samples((1..=100).rev().map(ms).collect());
+        assert_eq!(
+            stats,
+            TimingStats {
+                runs: 100,
+                min: ms(1),
+                median: ms(50),
+                p95: ms(95),
+            }
+        );
+
+        let single = TimingStats::from_samples(vec![ms(7)]);
+        assert_eq!(
+            (single.min, single.median, single.p95),
+            (ms(7), ms(7), ms(7))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_variants_alternate_and_are_timed() {
+        let mut order = Vec::new();
+        let comparison = compare_with("a", "b", 4, async |sql| {
+            order.push(sql.to_owned());
+            if sql == "a" {
+                tokio::time::sleep(ms(5)).await;
+            }
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(order, ["a", "b", "b", "a", "a", "b", "b", "a"]);
+        assert_eq!((comparison.a.runs, comparison.b.runs), (4, 4));
+        assert!(comparison.a.min >= ms(5));
+        assert!(comparison.a.median > comparison.b.median);
+    }
+
+    #[test]
+    fn test_compare_queries_against_database() {
+        test_util::run_with_db(|_| async {
+            let comparison = compare_queries("SELECT 1", "SELECT 1 + 1", 5)
+                .await
+                .unwrap();
+            for stats in [comparison.a, comparison.b] {
+                assert_eq!(stats.runs, 5);
+                assert!(stats.min > Duration::ZERO);
+                assert!(stats.min <= stats.median && stats.median <= stats.p95);
+            }
+        });
+    }
+}
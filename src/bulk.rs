@@ -0,0 +1,338 @@
+use serde_json::Value;
+use sqlx::{Encode, MySql, MySqlConnection, QueryBuilder, Type};
+
+use crate::{
+    db::{get_db_pool, with_pinned},
+    error::DbError,
+    sql::{json_arguments, quote_identifier},
+};
+
+/// Number of rows updated per statement.
+///
+/// Every row binds three parameters, which keeps a chunk far below both MySQL's placeholder
+/// limit and typical `max_allowed_packet` sizes.
+const BULK_UPDATE_CHUNK_SIZE: usize = 500;
+
+/// Maximum number of rows inserted per statement.
+const BULK_INSERT_CHUNK_SIZE: usize = 500;
+
+/// Number of placeholders a single MySQL prepared statement can hold.
+const MAX_PLACEHOLDERS: usize = 65_535;
+
+/// Updates many rows to individual values in as few round trips as possible.
+///
+/// For every `(key, new_value)` pair in `updates`, the row of `table` whose `key_col` equals
+/// `key` gets its `value_col` set to `new_value`. The updates are sent as
+/// `UPDATE ... SET value_col = CASE key_col WHEN ? THEN ? ... END WHERE key_col IN (...)`
+/// statements of at most 500 rows each. All values are bound as parameters, and the table and
+/// column names are validated with [`quote_identifier`].
+///
+/// The chunks are not wrapped in a transaction; run this inside one if the update must be
+/// all-or-nothing.
+///
+/// # Returns
+/// The total number of affected rows across all chunks.
+///
+/// # Example
+/// ```rust,no_run
+/// use zirv_db_sqlx::bulk::bulk_update;
+///
+/// async fn rename_users() -> Result<(), zirv_db_sqlx::error::DbError> {
+///     let updates = vec![(1_i64, "alice"), (2, "bob")];
+///     let affected = bulk_update("users", "id", &updates, "name").await?;
+///     println!("updated {} rows", affected);
+///     Ok(())
+/// }
+/// ```
+pub async fn bulk_update<K, V>(
+    table: &str,
+    key_col: &str,
+    updates: &[(K, V)],
+    value_col: &str,
+) -> Result<u64, DbError>
+where
+    K: for<'q> Encode<'q, MySql> + Type<MySql> + Sync + std::fmt::Debug,
+    V: for<'q> Encode<'q, MySql> + Type<MySql> + Sync + std::fmt::Debug,
+{
+    let mut affected = 0;
+    for chunk in updates.chunks(BULK_UPDATE_CHUNK_SIZE) {
+        let mut builder = build_bulk_update(table, key_col, chunk, value_col)?;
+        crate::statement_cache::track(builder.sql());
+        #[cfg(feature = "query-recorder")]
+        let started = std::time::Instant::now();
+        let rows = builder
+            .build()
+            .execute(get_db_pool())
+            .await?
+            .rows_affected();
+        #[cfg(feature = "query-recorder")]
+        {
+            // In the order of the placeholders: the `WHEN ? THEN ?` pairs, then the `IN` list.
+            let mut params: Vec<&dyn std::fmt::Debug> = Vec::with_capacity(chunk.len() * 3);
+            for (key, value) in chunk {
+                params.extend([key as &dyn std::fmt::Debug, value]);
+            }
+            params.extend(chunk.iter().map(|(key, _)| key as &dyn std::fmt::Debug));
+            crate::recorder::record_query(builder.sql(), &params, started.elapsed(), rows);
+        }
+        affected += rows;
+    }
+    Ok(affected)
+}
+
+/// Builds the `UPDATE ... CASE WHEN` statement for a single chunk of updates.
+fn build_bulk_update<'a, K, V>(
+    table: &str,
+    key_col: &str,
+    updates: &'a [(K, V)],
+    value_col: &str,
+) -> Result<QueryBuilder<'a, MySql>, DbError>
+where
+    K: for<'q> Encode<'q, MySql> + Type<MySql> + Sync,
+    V: for<'q> Encode<'q, MySql> + Type<MySql> + Sync,
+{
+    let table = quote_identifier(table)?;
+    let key_col = quote_identifier(key_col)?;
+    let value_col = quote_identifier(value_col)?;
+
+    let mut builder = QueryBuilder::new(format!(
+        "UPDATE {} SET {} = CASE {}",
+        table, value_col, key_col
+    ));
+    for (key, value) in updates {
+        builder.push(" WHEN ");
+        builder.push_bind(key);
+        builder.push(" THEN ");
+        builder.push_bind(value);
+    }
+    builder.push(format!(" ELSE {} END WHERE {} IN (", value_col, key_col));
+    let mut keys = builder.separated(", ");
+    for (key, _) in updates {
+        keys.push_bind(key);
+    }
+    keys.push_unseparated(")");
+
+    Ok(builder)
+}
+
+/// Inserts many rows and returns the auto-increment id generated for each of them.
+///
+/// Every row of `rows` holds one value per column of `columns`, bound as described in
+/// [`json_arguments`]; the table's auto-increment column must not be among them. The rows are
+/// sent as multi-row `INSERT` statements of at most 500 rows each (fewer for wide rows, to stay
+/// below MySQL's placeholder limit), all on one pinned connection. For every statement, MySQL
+/// reports the id of its first row (`LAST_INSERT_ID()`) and the number of inserted rows, from
+/// which the ids of the remaining rows are computed, stepping by the session's
+/// `auto_increment_increment`.
+///
+/// The chunks are not wrapped in a transaction; run this inside one if the insert must be
+/// all-or-nothing.
+///
+/// # Auto-increment gaps
+/// The computation assumes that the ids of a single statement are consecutive. InnoDB
+/// guarantees this for multi-row `INSERT ... VALUES` statements under
+/// `innodb_autoinc_lock_mode` 0 and 1. Under mode 2 (the default since MySQL 8.0), ids can
+/// interleave with those of concurrent bulk statements such as `INSERT ... SELECT` or
+/// `LOAD DATA` on the same table; do not rely on the returned ids if those run concurrently.
+///
+/// # Returns
+/// The generated ids, in the order of `rows`.
+///
+/// # Example
+/// ```rust,no_run
+/// use serde_json::json;
+/// use zirv_db_sqlx::bulk::bulk_insert_returning_ids;
+///
+/// async fn import_users() -> Result<Vec<u64>, zirv_db_sqlx::error::DbError> {
+///     let rows = [
+///         [json!("alice"), json!("alice@example.com")],
+///         [json!("bob"), json!("bob@example.com")],
+///     ];
+///     bulk_insert_returning_ids("users", ["name", "email"], &rows).await
+/// }
+/// ```
+pub async fn bulk_insert_returning_ids<const N: usize>(
+    table: &str,
+    columns: [&str; N],
+    rows: &[[Value; N]],
+) -> Result<Vec<u64>, DbError> {
+    let chunk_size = BULK_INSERT_CHUNK_SIZE.min(MAX_PLACEHOLDERS / N.max(1));
+    with_pinned(async |conn| insert_returning_ids(conn, table, &columns, rows, chunk_size).await)
+        .await
+}
+
+/// Inserts `rows` in chunks of `chunk_size` on `conn` and collects the generated ids.
+async fn insert_returning_ids<const N: usize>(
+    conn: &mut MySqlConnection,
+    table: &str,
+    columns: &[&str; N],
+    rows: &[[Value; N]],
+    chunk_size: usize,
+) -> Result<Vec<u64>, DbError> {
+    let increment: u64 =
+        sqlx::query_scalar("SELECT CAST(@@session.auto_increment_increment AS UNSIGNED)")
+            .fetch_one(&mut *conn)
+            .await?;
+
+    let mut ids = Vec::with_capacity(rows.len());
+    for chunk in rows.chunks(chunk_size) {
+        let sql = build_bulk_insert(table, columns, chunk.len())?;
+        let args = json_arguments(chunk.as_flattened())?;
+        crate::statement_cache::track(&sql);
+        #[cfg(feature = "query-recorder")]
+        let started = std::time::Instant::now();
+        let result = sqlx::query_with(&sql, args).execute(&mut *conn).await?;
+        #[cfg(feature = "query-recorder")]
+        crate::recorder::record_values(&sql, chunk.as_flattened(), started, result.rows_affected());
+        let first = result.last_insert_id();
+        ids.extend((0..result.rows_affected()).map(|i| first + i * increment));
+    }
+    Ok(ids)
+}
+
+/// Builds the multi-row `INSERT` statement for `rows` rows of `columns`.
+fn build_bulk_insert(table: &str, columns: &[&str], rows: usize) -> Result<String, DbError> {
+    let table = quote_identifier(table)?;
+    let columns = columns
+        .iter()
+        .map(|column| quote_identifier(column))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let placeholders = format!("({})", vec!["?"; columns.len()].join(", "));
+    Ok(format!(
+        "INSERT INTO {} ({}) VALUES {}",
+        table,
+        columns.join(", "),
+        vec![placeholders; rows].join(", ")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+    use serde_json::json;
+
+    #[test]
+    fn test_build_bulk_update_sql() {
+        let updates = [(1_i64, "a"), (2, "b")];
+        let builder = build_bulk_update("users", "id", &updates, "name").unwrap();
+        assert_eq!(
+            builder.sql(),
+            "UPDATE `users` SET `name` = CASE `id` WHEN ? THEN ? WHEN ? THEN ? \
+             ELSE `name` END WHERE `id` IN (?, ?)"
+        );
+    }
+
+    #[test]
+    fn test_build_bulk_update_rejects_invalid_identifiers() {
+        let updates = [(1_i64, "a")];
+        assert!(matches!(
+            build_bulk_update("users; DROP TABLE users", "id", &updates, "name"),
+            Err(DbError::InvalidIdentifier(_))
+        ));
+    }
+
+    #[test]
+    fn test_bulk_update_distinct_values() {
+        test_util::run_with_db(|pool| async move {
+            sqlx::query("DROP TABLE IF EXISTS zirv_test_bulk_update")
+                .execute(pool)
+                .await
+                .unwrap();
+            sqlx::query("CREATE TABLE zirv_test_bulk_update (id BIGINT PRIMARY KEY, value BIGINT)")
+                .execute(pool)
+                .await
+                .unwrap();
+            for id in 0..100_i64 {
+                sqlx::query("INSERT INTO zirv_test_bulk_update (id, value) VALUES (?, 0)")
+                    .bind(id)
+                    .execute(pool)
+                    .await
+                    .unwrap();
+            }
+
+            let updates: Vec<(i64, i64)> = (0..100).map(|id| (id, id * 10)).collect();
+            let affected = bulk_update("zirv_test_bulk_update", "id", &updates, "value")
+                .await
+                .unwrap();
+            assert_eq!(affected, 100);
+
+            let rows: Vec<(i64, i64)> =
+                sqlx::query_as("SELECT id, value FROM zirv_test_bulk_update ORDER BY id")
+                    .fetch_all(pool)
+                    .await
+                    .unwrap();
+            assert_eq!(rows, updates);
+
+            sqlx::query("DROP TABLE zirv_test_bulk_update")
+                .execute(pool)
+                .await
+                .unwrap();
+        });
+    }
+
+    #[test]
+    fn test_build_bulk_insert_sql() {
+        assert_eq!(
+            build_bulk_insert("users", &["name", "email"], 2).unwrap(),
+            "INSERT INTO `users` (`name`, `email`) VALUES (?, ?), (?, ?)"
+        );
+        assert!(matches!(
+            build_bulk_insert("users", &["name); DROP TABLE users; --"], 1),
+            Err(DbError::InvalidIdentifier(_))
+        ));
+    }
+
+    #[test]
+    fn test_bulk_insert_returning_ids_matches_inserted_rows() {
+        test_util::run_with_db(|pool| async move {
+            sqlx::query("DROP TABLE IF EXISTS zirv_test_bulk_insert")
+                .execute(pool)
+                .await
+                .unwrap();
+            sqlx::query(
+                "CREATE TABLE zirv_test_bulk_insert \
+                 (id BIGINT UNSIGNED AUTO_INCREMENT PRIMARY KEY, name VARCHAR(16) NOT NULL)",
+            )
+            .execute(pool)
+            .await
+            .unwrap();
+            // Leave a gap before the first generated id.
+            sqlx::query("ALTER TABLE zirv_test_bulk_insert AUTO_INCREMENT = 100")
+                .execute(pool)
+                .await
+                .unwrap();
+
+            let rows: Vec<[Value; 1]> = (0..5).map(|i| [json!(format!("row {}", i))]).collect();
+            // Chunks of two rows, so the ids span three statements.
+            let mut conn = pool.acquire().await.unwrap();
+            let ids = insert_returning_ids(&mut conn, "zirv_test_bulk_insert", &["name"], &rows, 2)
+                .await
+                .unwrap();
+            drop(conn);
+            let more = bulk_insert_returning_ids("zirv_test_bulk_insert", ["name"], &rows[..1])
+                .await
+                .unwrap();
+
+            let stored: Vec<(u64, String)> =
+                sqlx::query_as("SELECT id, name FROM zirv_test_bulk_insert ORDER BY id")
+                    .fetch_all(pool)
+                    .await
+                    .unwrap();
+            let expected: Vec<(u64, String)> = ids
+                .iter()
+                .chain(&more)
+                .zip(rows.iter().chain(&rows[..1]))
+                .map(|(id, [name])| (*id, name.as_str().unwrap().to_owned()))
+                .collect();
+            assert_eq!(ids, [100, 101, 102, 103, 104]);
+            assert_eq!(stored, expected);
+
+            sqlx::query("DROP TABLE zirv_test_bulk_insert")
+                .execute(pool)
+                .await
+                .unwrap();
+        });
+    }
+}
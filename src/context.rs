@@ -0,0 +1,96 @@
+use sqlx::{MySql, Pool};
+
+use crate::db::{get_read_pool, get_write_pool};
+
+/// Gives access to the pools for reading and writing.
+///
+/// Application code that depends on this trait instead of calling [`get_read_pool`] and
+/// [`get_write_pool`] directly can be handed a different implementation in tests.
+///
+/// # Implementing a custom context
+/// A test context only needs to hold its own pools:
+///
+/// ```rust
+/// use sqlx::{MySql, Pool};
+/// use zirv_db_sqlx::context::DbContext;
+///
+/// struct TestDbContext {
+///     read: Pool<MySql>,
+///     write: Pool<MySql>,
+/// }
+///
+/// impl DbContext for TestDbContext {
+///     fn read(&self) -> &Pool<MySql> {
+///         &self.read
+///     }
+///
+///     fn write(&self) -> &Pool<MySql> {
+///         &self.write
+///     }
+/// }
+///
+/// async fn count_users(db: &impl DbContext) -> Result<i64, sqlx::Error> {
+///     sqlx::query_scalar("SELECT COUNT(*) FROM users")
+///         .fetch_one(db.read())
+///         .await
+/// }
+/// ```
+pub trait DbContext {
+    /// Returns the pool to use for read-only queries.
+    fn read(&self) -> &Pool<MySql>;
+
+    /// Returns the pool to use for writes.
+    fn write(&self) -> &Pool<MySql>;
+}
+
+/// The default context, backed by the global pools set up by `init_db_pool`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GlobalDbContext;
+
+impl DbContext for GlobalDbContext {
+    fn read(&self) -> &Pool<MySql> {
+        get_read_pool()
+    }
+
+    fn write(&self) -> &Pool<MySql> {
+        get_write_pool()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{database_of, lazy_pool};
+
+    struct MockDbContext {
+        read: Pool<MySql>,
+        write: Pool<MySql>,
+    }
+
+    impl DbContext for MockDbContext {
+        fn read(&self) -> &Pool<MySql> {
+            &self.read
+        }
+
+        fn write(&self) -> &Pool<MySql> {
+            &self.write
+        }
+    }
+
+    fn pools_used(db: &impl DbContext) -> (String, String) {
+        (database_of(db.read()), database_of(db.write()))
+    }
+
+    #[tokio::test]
+    async fn test_mock_context_returns_distinct_pools() {
+        let db = MockDbContext {
+            read: lazy_pool("app_read"),
+            write: lazy_pool("app_write"),
+        };
+
+        assert_eq!(
+            pools_used(&db),
+            ("app_read".to_owned(), "app_write".to_owned())
+        );
+    }
+}
@@ -0,0 +1,187 @@
+use serde_json::Value;
+
+use crate::{
+    db::get_db_pool,
+    error::DbError,
+    sql::{json_arguments, quote_identifier},
+};
+
+/// Builds and runs `SELECT COUNT(*)` queries with optional equality filters.
+///
+/// Filters are combined with `AND`. Column and table names are validated with
+/// [`quote_identifier`] and values are always bound as parameters; a `null` value matches rows
+/// where the column `IS NULL`.
+///
+/// # Example
+/// ```rust,no_run
+/// use zirv_db_sqlx::count::CountQuery;
+///
+/// async fn active_admins() -> Result<i64, zirv_db_sqlx::error::DbError> {
+///     CountQuery::new("users")
+///         .filter("active", true)
+///         .filter("role", "admin")
+///         .count()
+///         .await
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct CountQuery {
+    table: String,
+    filters: Vec<(String, Value)>,
+}
+
+impl CountQuery {
+    /// Starts a count over all rows of `table`.
+    pub fn new(table: impl Into<String>) -> Self {
+        CountQuery {
+            table: table.into(),
+            filters: Vec::new(),
+        }
+    }
+
+    /// Only counts rows where `column` equals `value`.
+    pub fn filter(mut self, column: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.filters.push((column.into(), value.into()));
+        self
+    }
+
+    /// Only counts rows where `column` equals `value`, if a value is given.
+    ///
+    /// Convenient for optional filters coming from a request.
+    pub fn filter_opt(self, column: impl Into<String>, value: Option<impl Into<Value>>) -> Self {
+        match value {
+            Some(value) => self.filter(column, value),
+            None => self,
+        }
+    }
+
+    /// Builds the SQL and the values to bind to it.
+    pub fn build(&self) -> Result<(String, Vec<Value>), DbError> {
+        let mut sql = format!("SELECT COUNT(*) FROM {}", quote_identifier(&self.table)?);
+        let mut binds = Vec::new();
+
+        for (i, (column, value)) in self.filters.iter().enumerate() {
+            sql.push_str(if i == 0 { " WHERE " } else { " AND " });
+            sql.push_str(&quote_identifier(column)?);
+            if value.is_null() {
+                sql.push_str(" IS NULL");
+            } else {
+                sql.push_str(" = ?");
+                binds.push(value.clone());
+            }
+        }
+
+        Ok((sql, binds))
+    }
+
+    /// Runs the count against the global pool.
+    pub async fn count(&self) -> Result<i64, DbError> {
+        let (sql, binds) = self.build()?;
+        crate::statement_cache::track(&sql);
+        #[cfg(feature = "query-recorder")]
+        let started = std::time::Instant::now();
+
+        let count: i64 = sqlx::query_scalar_with(&sql, json_arguments(&binds)?)
+            .fetch_one(get_db_pool())
+            .await?;
+
+        #[cfg(feature = "query-recorder")]
+        crate::recorder::record_values(&sql, &binds, started, 1);
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+    use serde_json::json;
+
+    #[test]
+    fn test_build_without_filters() {
+        let (sql, binds) = CountQuery::new("users").build().unwrap();
+        assert_eq!(sql, "SELECT COUNT(*) FROM `users`");
+        assert!(binds.is_empty());
+    }
+
+    #[test]
+    fn test_build_with_qualified_table() {
+        let (sql, _) = CountQuery::new("reporting.events").build().unwrap();
+        assert_eq!(sql, "SELECT COUNT(*) FROM `reporting`.`events`");
+        assert!(CountQuery::new("a.b.c").build().is_err());
+    }
+
+    #[test]
+    fn test_build_with_one_filter() {
+        let (sql, binds) = CountQuery::new("users")
+            .filter("active", true)
+            .build()
+            .unwrap();
+        assert_eq!(sql, "SELECT COUNT(*) FROM `users` WHERE `active` = ?");
+        assert_eq!(binds, [json!(true)]);
+    }
+
+    #[test]
+    fn test_build_with_multiple_filters() {
+        let (sql, binds) = CountQuery::new("users")
+            .filter("active", true)
+            .filter("role", "admin")
+            .filter("deleted_at", Value::Null)
+            .filter_opt("team_id", None::<i64>)
+            .build()
+            .unwrap();
+        assert_eq!(
+            sql,
+            "SELECT COUNT(*) FROM `users` WHERE `active` = ? AND `role` = ? AND `deleted_at` IS NULL"
+        );
+        assert_eq!(binds, [json!(true), json!("admin")]);
+    }
+
+    #[test]
+    fn test_build_rejects_invalid_columns() {
+        let query = CountQuery::new("users").filter("1 = 1 OR active", true);
+        assert!(matches!(query.build(), Err(DbError::InvalidIdentifier(_))));
+    }
+
+    #[test]
+    fn test_count_against_database() {
+        test_util::run_with_db(|pool| async move {
+            sqlx::query("DROP TABLE IF EXISTS zirv_test_count")
+                .execute(pool)
+                .await
+                .unwrap();
+            sqlx::query(
+                "CREATE TABLE zirv_test_count (id INT PRIMARY KEY, role VARCHAR(16), active BOOLEAN)",
+            )
+            .execute(pool)
+            .await
+            .unwrap();
+            sqlx::query(
+                "INSERT INTO zirv_test_count VALUES \
+                 (1, 'admin', TRUE), (2, 'admin', FALSE), (3, 'user', TRUE)",
+            )
+            .execute(pool)
+            .await
+            .unwrap();
+
+            let all = CountQuery::new("zirv_test_count").count().await.unwrap();
+            let admins = CountQuery::new("zirv_test_count")
+                .filter("role", "admin")
+                .count()
+                .await
+                .unwrap();
+            let active_admins = CountQuery::new("zirv_test_count")
+                .filter("role", "admin")
+                .filter("active", true)
+                .count()
+                .await
+                .unwrap();
+            assert_eq!((all, admins, active_admins), (3, 2, 1));
+
+            sqlx::query("DROP TABLE zirv_test_count")
+                .execute(pool)
+                .await
+                .unwrap();
+        });
+    }
+}
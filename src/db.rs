@@ -1,27 +1,43 @@
-use std::sync::OnceLock;
-use sqlx::{Pool, MySql, mysql::MySqlPoolOptions};
-use zirv_config::read_config;
+use sqlx::{
+    MySql, MySqlConnection, Pool,
+    mysql::{MySqlConnectOptions, MySqlPoolOptions, MySqlSslMode},
+};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::config::{PoolConfig, record_init_config, resolve_pool_config};
+use crate::idle_stack::{self, Acquired, acquire_from};
+
+/// Query run by `database.validate_on_connect` when no `database.validation_query` is set.
+const DEFAULT_VALIDATION_QUERY: &str = "SELECT 1";
 
 // Our global, one-time-initialized pool
 static DB_POOL: OnceLock<Pool<MySql>> = OnceLock::new();
 
+// The optional pool for read-only queries, connected to `database.read_url`
+static READ_POOL: OnceLock<Pool<MySql>> = OnceLock::new();
+
 /// Initializes the global database pool exactly once.
-/// 
+///
 /// This function should be called early in your application's lifecycle (for example, in your `main` function).
-/// It reads the configuration for the maximum number of database connections and the database URL.
-/// If no value is provided for the maximum connections, it defaults to 10.
-/// 
+/// It reads the configuration for the maximum number of database connections and the database URL,
+/// which may also come from the `DATABASE_URL` environment variable (see [`resolve_pool_config`]). If no value is provided for the maximum connections, it defaults to 10.
+///
+/// If `database.read_url` is configured (for example pointing at a replica), a second pool with the
+/// same settings is created for it and returned by [`get_read_pool`].
+///
 /// # Panics
-/// - If no database URL is provided in the configuration.
+/// - If no database URL is provided in the configuration, or a setting has an invalid value.
+/// - If the database URL cannot be parsed.
 /// - If the pool fails to be created.
 /// - If the global pool is already initialized.
 pub async fn init_db_pool() {
-    let max_database_connections: u32 = read_config!("database.max_connections", u32).unwrap_or(10);
-    let database_url = read_config!("database.url", String).unwrap();
-
-    let pool = MySqlPoolOptions::new()
-        .max_connections(max_database_connections)
-        .connect(&database_url)
+    let config = resolve_pool_config().expect("Invalid database configuration.");
+    let options = connect_options(&config, &config.url).expect("Invalid database URL.");
+    let pool = pool_options(&config)
+        .connect_with(options.clone())
         .await
         .expect("Failed to create MySQL pool.");
 
@@ -29,13 +45,168 @@ pub async fn init_db_pool() {
     DB_POOL
         .set(pool)
         .expect("DB_POOL can only be initialized once!");
+
+    if let Some(read_url) = &config.read_url {
+        let read_pool = connect(&config, read_url)
+            .await
+            .expect("Failed to create MySQL read pool.");
+        READ_POOL
+            .set(read_pool)
+            .expect("READ_POOL can only be initialized once!");
+    }
+
+    idle_stack::init(&config);
+    record_init_config(&config, &options);
+}
+
+/// Builds the connect options for `url` from the resolved configuration.
+pub(crate) fn connect_options(
+    config: &PoolConfig,
+    url: &str,
+) -> Result<MySqlConnectOptions, sqlx::Error> {
+    let mut options = MySqlConnectOptions::from_str(url)?
+        .statement_cache_capacity(config.statement_cache_capacity);
+    if let Some(ssl_mode) = &config.ssl_mode {
+        options = options.ssl_mode(MySqlSslMode::from_str(ssl_mode)?);
+    }
+    Ok(options)
+}
+
+/// Builds the pool options from the resolved configuration.
+///
+/// With `database.force_utc`, every new connection runs `SET time_zone = '+00:00'` before it is
+/// handed out. sqlx already requests UTC by default, but a `timezone` parameter in the URL (or
+/// in the connect options) overrides that; `force_utc` guarantees that `TIMESTAMP` columns and
+/// `chrono::DateTime<Utc>` / `time::OffsetDateTime` values round-trip without an offset being
+/// applied.
+///
+/// With `database.app_version`, every new connection also stores that label in the
+/// `@_app_version` session variable, so load can be grouped by deployment through
+/// `performance_schema.user_variables_by_thread`. sqlx does not let clients send their own
+/// connection attributes, so the label does not appear in `session_connect_attrs`.
+///
+/// With `database.max_connects_per_sec`, new connections are spaced out so that at most that
+/// many per second are handed out, e.g. when every client reconnects after a database restart.
+/// sqlx has no hook that runs before a connection is opened, so the wait happens at the start
+/// of `after_connect`: the handshakes themselves are not delayed, but the queries waiting for
+/// the new connections are released gradually instead of all at once.
+///
+/// Idle connections are validated before they are handed out. By default sqlx pings them;
+/// with `database.validation_query`, that query is run instead (through the text protocol, so
+/// it is not prepared), and a connection for which it fails is closed and replaced. With
+/// `database.validate_on_connect`, the validation query (`SELECT 1` if none is configured) also
+/// runs at the end of `after_connect`, so a new connection that cannot answer it, such as one
+/// routed to a broken node behind a proxy, fails to connect instead of being handed out.
+///
+/// `database.acquire_order` is not applied here: sqlx keeps idle connections in a FIFO queue,
+/// so LIFO order is provided by a stack in front of the global pool, see
+/// [`AcquireOrder`](crate::config::AcquireOrder).
+pub(crate) fn pool_options(config: &PoolConfig) -> MySqlPoolOptions {
+    let force_utc = config.force_utc;
+    let app_version = config.app_version.clone();
+    let connect_validation = config.validate_on_connect.then(|| {
+        config
+            .validation_query
+            .clone()
+            .unwrap_or_else(|| DEFAULT_VALIDATION_QUERY.to_owned())
+    });
+    let limiter = ConnectLimiter::new(config.max_connects_per_sec).map(Arc::new);
+
+    let options = MySqlPoolOptions::new()
+        .max_connections(config.max_connections)
+        .min_connections(config.min_connections)
+        .acquire_timeout(Duration::from_secs(config.acquire_timeout_secs))
+        .idle_timeout(non_zero_secs(config.idle_timeout_secs))
+        .max_lifetime(non_zero_secs(config.max_lifetime_secs))
+        .after_connect(move |conn, _meta| {
+            let app_version = app_version.clone();
+            let limiter = limiter.clone();
+            let connect_validation = connect_validation.clone();
+            Box::pin(async move {
+                if let Some(limiter) = limiter {
+                    limiter.wait().await;
+                }
+                if force_utc {
+                    sqlx::query("SET time_zone = '+00:00'")
+                        .execute(&mut *conn)
+                        .await?;
+                }
+                if let Some(app_version) = app_version {
+                    sqlx::query("SET @_app_version = ?")
+                        .bind(app_version)
+                        .execute(&mut *conn)
+                        .await?;
+                }
+                if let Some(validation_query) = connect_validation {
+                    sqlx::Executor::execute(&mut *conn, validation_query.as_str()).await?;
+                }
+                Ok(())
+            })
+        });
+
+    match config.validation_query.clone() {
+        Some(validation_query) => {
+            options
+                .test_before_acquire(false)
+                .before_acquire(move |conn, _meta| {
+                    let validation_query = validation_query.clone();
+                    Box::pin(async move {
+                        sqlx::Executor::execute(&mut *conn, validation_query.as_str()).await?;
+                        Ok(true)
+                    })
+                })
+        }
+        None => options,
+    }
+}
+
+/// Spaces out events so that at most a given number happen per second.
+///
+/// This is a token bucket holding a single token: each caller reserves the next free slot,
+/// one interval after the previous one, and sleeps until then.
+struct ConnectLimiter {
+    interval: Duration,
+    next_slot: Mutex<Instant>,
+}
+
+impl ConnectLimiter {
+    /// Creates a limiter for `per_sec` events per second, or `None` if `per_sec` is `0`.
+    fn new(per_sec: u32) -> Option<Self> {
+        (per_sec > 0).then(|| ConnectLimiter {
+            interval: Duration::from_secs(1) / per_sec,
+            next_slot: Mutex::new(Instant::now()),
+        })
+    }
+
+    /// Waits until the caller's slot has come.
+    async fn wait(&self) {
+        let slot = {
+            let mut next_slot = self.next_slot.lock().unwrap_or_else(|e| e.into_inner());
+            let slot = (*next_slot).max(Instant::now());
+            *next_slot = slot + self.interval;
+            slot
+        };
+        tokio::time::sleep_until(slot).await;
+    }
+}
+
+/// Converts a number of seconds into an optional duration, where `0` means "disabled".
+pub(crate) fn non_zero_secs(secs: u64) -> Option<Duration> {
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+/// Connects a new pool to `url` using the resolved configuration.
+pub(crate) async fn connect(config: &PoolConfig, url: &str) -> Result<Pool<MySql>, sqlx::Error> {
+    pool_options(config)
+        .connect_with(connect_options(config, url)?)
+        .await
 }
 
 /// Retrieves a reference to the global database pool.
 ///
 /// # Panics
 /// Panics if `init_db_pool` has not been called, as the pool will not be initialized.
-/// 
+///
 /// # Returns
 /// A reference to the initialized `Pool<MySql>`.
 pub fn get_db_pool() -> &'static Pool<MySql> {
@@ -43,3 +214,356 @@ pub fn get_db_pool() -> &'static Pool<MySql> {
         .get()
         .expect("DB pool not initialized! Call init_db_pool first.")
 }
+
+/// Retrieves the pool to use for read-only queries.
+///
+/// This is the pool connected to `database.read_url`, or the global pool if no read URL is
+/// configured.
+///
+/// # Panics
+/// Panics if `init_db_pool` has not been called.
+pub fn get_read_pool() -> &'static Pool<MySql> {
+    READ_POOL.get().unwrap_or_else(get_db_pool)
+}
+
+/// Returns the pool connected to `database.read_url`, if one is configured.
+pub(crate) fn replica_pool() -> Option<&'static Pool<MySql>> {
+    READ_POOL.get()
+}
+
+/// Retrieves the pool to use for writes, which is always the global (primary) pool.
+///
+/// # Panics
+/// Panics if `init_db_pool` has not been called.
+pub fn get_write_pool() -> &'static Pool<MySql> {
+    get_db_pool()
+}
+
+/// Runs `f` with a single connection from the global pool.
+///
+/// Every query in `f` runs on the same connection, so session state carries over between them:
+/// `LAST_INSERT_ID()`, temporary tables, session variables and `SET SESSION` settings. Unlike
+/// [`with_transaction`](crate::transaction::with_transaction), no transaction is started:
+/// autocommit stays on, each statement is committed as soon as it succeeds and nothing is
+/// rolled back if `f` fails.
+///
+/// The connection goes back to the pool afterwards with its session state intact, so drop
+/// temporary tables and reset session settings that other users of the pool must not see.
+///
+/// # Example
+/// ```rust,no_run
+/// use zirv_db_sqlx::db::with_pinned;
+///
+/// async fn create_user() -> Result<u64, sqlx::Error> {
+///     with_pinned(async |conn| {
+///         sqlx::query("INSERT INTO users (name) VALUES (?)")
+///             .bind("Jane Doe")
+///             .execute(&mut *conn)
+///             .await?;
+///         sqlx::query_scalar("SELECT LAST_INSERT_ID()")
+///             .fetch_one(&mut *conn)
+///             .await
+///     })
+///     .await
+/// }
+/// ```
+pub async fn with_pinned<F, T, E>(f: F) -> Result<T, E>
+where
+    F: AsyncFnOnce(&mut MySqlConnection) -> Result<T, E>,
+    E: From<sqlx::Error>,
+{
+    let mut conn = acquire().await?;
+    f(&mut conn).await
+}
+
+/// Takes a connection from the global pool, most recently released first with
+/// `database.acquire_order = "lifo"`.
+pub(crate) async fn acquire() -> Result<Acquired, sqlx::Error> {
+    acquire_from(get_db_pool(), idle_stack::global()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AcquireOrder, EffectiveConfig};
+    use crate::idle_stack::IdleStack;
+    use crate::test_util;
+    use chrono::{DateTime, TimeZone, Utc};
+    use serde_json::json;
+
+    #[test]
+    fn test_force_utc_round_trips_timestamps() {
+        test_util::run_with_db(|_| async {
+            let url = test_util::database_url().unwrap();
+            let config = PoolConfig::from_value(&json!({ "url": url, "force_utc": true })).unwrap();
+            // A non-UTC session time zone that `force_utc` has to override.
+            let options = connect_options(&config, &url)
+                .unwrap()
+                .timezone(Some("+02:00".to_owned()));
+            let pool = pool_options(&config).connect_with(options).await.unwrap();
+
+            let time_zone: String = sqlx::query_scalar("SELECT @@session.time_zone")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+            assert_eq!(time_zone, "+00:00");
+
+            let known: DateTime<Utc> = Utc.with_ymd_and_hms(2024, 3, 15, 12, 30, 0).unwrap();
+            let mut conn = pool.acquire().await.unwrap();
+            sqlx::query("CREATE TEMPORARY TABLE zirv_test_utc (ts TIMESTAMP NOT NULL)")
+                .execute(&mut *conn)
+                .await
+                .unwrap();
+            sqlx::query("INSERT INTO zirv_test_utc (ts) VALUES (?)")
+                .bind(known)
+                .execute(&mut *conn)
+                .await
+                .unwrap();
+
+            let (read_back, epoch): (DateTime<Utc>, i64) =
+                sqlx::query_as("SELECT ts, UNIX_TIMESTAMP(ts) FROM zirv_test_utc")
+                    .fetch_one(&mut *conn)
+                    .await
+                    .unwrap();
+            assert_eq!(read_back, known);
+            assert_eq!(epoch, known.timestamp());
+        });
+    }
+
+    #[test]
+    fn test_app_version_is_set_on_new_connections() {
+        test_util::run_with_db(|_| async {
+            let url = test_util::database_url().unwrap();
+            let config =
+                PoolConfig::from_value(&json!({ "url": url, "app_version": "zirv-test-1.2.3" }))
+                    .unwrap();
+            let pool = pool_options(&config)
+                .connect_with(connect_options(&config, &url).unwrap())
+                .await
+                .unwrap();
+
+            let version: String = sqlx::query_scalar("SELECT @_app_version")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+            assert_eq!(version, "zirv-test-1.2.3");
+
+            // Where performance_schema is enabled and readable, DBAs see the label per thread.
+            let exposed: Result<Option<String>, _> = sqlx::query_scalar(
+                "SELECT CAST(VARIABLE_VALUE AS CHAR) \
+                 FROM performance_schema.user_variables_by_thread \
+                 WHERE THREAD_ID = PS_CURRENT_THREAD_ID() AND VARIABLE_NAME = '_app_version'",
+            )
+            .fetch_optional(&pool)
+            .await;
+            if let Ok(Some(exposed)) = exposed {
+                assert_eq!(exposed, "zirv-test-1.2.3");
+            }
+
+            // Without a label, nothing is set.
+            let config = PoolConfig::from_value(&json!({ "url": url })).unwrap();
+            let pool = pool_options(&config)
+                .connect_with(connect_options(&config, &url).unwrap())
+                .await
+                .unwrap();
+            let version: Option<String> = sqlx::query_scalar("SELECT @_app_version")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+            assert_eq!(version, None);
+        });
+    }
+
+    #[tokio::test]
+    async fn test_connect_limiter_spaces_out_callers() {
+        assert!(ConnectLimiter::new(0).is_none());
+
+        let limiter = Arc::new(ConnectLimiter::new(20).unwrap());
+        let start = Instant::now();
+        let waits = (0..4).map(|_| {
+            let limiter = limiter.clone();
+            tokio::spawn(async move {
+                limiter.wait().await;
+                start.elapsed()
+            })
+        });
+        let mut elapsed = Vec::new();
+        for wait in waits.collect::<Vec<_>>() {
+            elapsed.push(wait.await.unwrap());
+        }
+        elapsed.sort();
+
+        // The first caller passes immediately, the others one interval (50ms) apart.
+        assert!(elapsed[0] < Duration::from_millis(50), "{:?}", elapsed);
+        for (i, elapsed) in elapsed.iter().enumerate().skip(1) {
+            assert!(
+                *elapsed >= Duration::from_millis(50) * i as u32,
+                "{:?}",
+                elapsed
+            );
+        }
+    }
+
+    #[test]
+    fn test_max_connects_per_sec_spaces_out_new_connections() {
+        test_util::run_with_db(|_| async {
+            let url = test_util::database_url().unwrap();
+            let config =
+                PoolConfig::from_value(&json!({ "url": url, "max_connects_per_sec": 5 })).unwrap();
+            let pool =
+                pool_options(&config).connect_lazy_with(connect_options(&config, &url).unwrap());
+
+            let start = std::time::Instant::now();
+            let conns = futures_util::future::try_join_all((0..3).map(|_| pool.acquire()))
+                .await
+                .unwrap();
+            // Three new connections at 5 per second: the last one waits two 200ms intervals.
+            assert_eq!(conns.len(), 3);
+            assert!(start.elapsed() >= Duration::from_millis(400));
+        });
+    }
+
+    #[test]
+    fn test_validation_query_runs_before_acquire() {
+        test_util::run_with_db(|_| async {
+            let url = test_util::database_url().unwrap();
+            let config = PoolConfig::from_value(&json!({
+                "url": url,
+                "max_connections": 1,
+                "validation_query": "SET @_zirv_validated = 1",
+            }))
+            .unwrap();
+            let pool = pool_options(&config)
+                .connect_with(connect_options(&config, &url).unwrap())
+                .await
+                .unwrap();
+
+            // A new connection is not validated; the idle one handed out again is.
+            let validated: Option<i64> = sqlx::query_scalar("SELECT @_zirv_validated")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+            assert_eq!(validated, None);
+            let validated: Option<i64> = sqlx::query_scalar("SELECT @_zirv_validated")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+            assert_eq!(validated, Some(1));
+        });
+    }
+
+    #[test]
+    fn test_validate_on_connect_runs_on_new_connections() {
+        test_util::run_with_db(|_| async {
+            let url = test_util::database_url().unwrap();
+            let config = PoolConfig::from_value(&json!({
+                "url": url,
+                "validation_query": "SET @_zirv_connect_validated = 1",
+                "validate_on_connect": true,
+            }))
+            .unwrap();
+            let pool = pool_options(&config)
+                .connect_with(connect_options(&config, &url).unwrap())
+                .await
+                .unwrap();
+            // Two connections at once, so the second one is new as well.
+            let mut a = pool.acquire().await.unwrap();
+            let mut b = pool.acquire().await.unwrap();
+            for conn in [&mut a, &mut b] {
+                let validated: Option<i64> = sqlx::query_scalar("SELECT @_zirv_connect_validated")
+                    .fetch_one(&mut **conn)
+                    .await
+                    .unwrap();
+                assert_eq!(validated, Some(1));
+            }
+
+            let config = PoolConfig::from_value(&json!({
+                "url": url,
+                "validation_query": "SELECT * FROM zirv_test_missing_table",
+                "validate_on_connect": true,
+                "acquire_timeout_secs": 1,
+            }))
+            .unwrap();
+            let result = pool_options(&config)
+                .connect_with(connect_options(&config, &url).unwrap())
+                .await;
+            assert!(result.is_err());
+        });
+    }
+
+    #[test]
+    fn test_lifo_acquire_order_reuses_the_last_released_connection() {
+        test_util::run_with_db(|_| async {
+            let url = test_util::database_url().unwrap();
+            let config = PoolConfig::from_value(&json!({
+                "url": url,
+                "max_connections": 4,
+                "acquire_order": "lifo",
+            }))
+            .unwrap();
+            let options = connect_options(&config, &url).unwrap();
+            assert_eq!(
+                EffectiveConfig::new(&config, &options).acquire_order,
+                AcquireOrder::Lifo
+            );
+            let pool = pool_options(&config).connect_with(options).await.unwrap();
+            let stack: &'static _ = Box::leak(Box::new(IdleStack::for_config(&config).unwrap()));
+            let connection_id = async |conn: &mut Acquired| -> u64 {
+                sqlx::query_scalar("SELECT CONNECTION_ID()")
+                    .fetch_one(&mut **conn)
+                    .await
+                    .unwrap()
+            };
+
+            let mut a = acquire_from(&pool, Some(stack)).await.unwrap();
+            let mut b = acquire_from(&pool, Some(stack)).await.unwrap();
+            let (a_id, b_id) = (connection_id(&mut a).await, connection_id(&mut b).await);
+            drop(a);
+            drop(b);
+
+            for _ in 0..3 {
+                let mut conn = acquire_from(&pool, Some(stack)).await.unwrap();
+                assert_eq!(connection_id(&mut conn).await, b_id);
+            }
+            let mut first = acquire_from(&pool, Some(stack)).await.unwrap();
+            let mut second = acquire_from(&pool, Some(stack)).await.unwrap();
+            assert_eq!(connection_id(&mut first).await, b_id);
+            assert_eq!(connection_id(&mut second).await, a_id);
+        });
+    }
+
+    #[test]
+    fn test_with_pinned_keeps_last_insert_id() {
+        test_util::run_with_db(|_| async {
+            let (id, read_back) = with_pinned(async |conn| {
+                sqlx::query(
+                    "CREATE TEMPORARY TABLE zirv_test_pinned \
+                     (id BIGINT UNSIGNED AUTO_INCREMENT PRIMARY KEY, name VARCHAR(16))",
+                )
+                .execute(&mut *conn)
+                .await?;
+                sqlx::query("INSERT INTO zirv_test_pinned (name) VALUES ('a'), ('b')")
+                    .execute(&mut *conn)
+                    .await?;
+                let id: u64 = sqlx::query_scalar("SELECT LAST_INSERT_ID()")
+                    .fetch_one(&mut *conn)
+                    .await?;
+                let name: String =
+                    sqlx::query_scalar("SELECT name FROM zirv_test_pinned WHERE id = ?")
+                        .bind(id)
+                        .fetch_one(&mut *conn)
+                        .await?;
+                sqlx::query("DROP TEMPORARY TABLE zirv_test_pinned")
+                    .execute(&mut *conn)
+                    .await?;
+                Ok::<_, sqlx::Error>((id, name))
+            })
+            .await
+            .unwrap();
+
+            // For a multi-row insert, LAST_INSERT_ID() is the id of the first row.
+            assert_eq!(id, 1);
+            assert_eq!(read_back, "a");
+        });
+    }
+}
@@ -0,0 +1,164 @@
+use sqlx::FromRow;
+use sqlx::mysql::{MySqlArguments, MySqlRow};
+
+use crate::db::get_db_pool;
+
+/// Runs a typed query against the global pool, explaining decode failures when possible.
+///
+/// This is the function behind [`query_as_diag!`](crate::query_as_diag). It behaves exactly like
+/// `sqlx::query_as_with(sql, args).fetch_all(pool)`. With the `diagnostics` feature enabled, a
+/// failure to decode a column into `T` additionally re-runs the query untyped and logs a single
+/// line with the name and MySQL type of the offending column and the value of the first row
+/// that fails to decode (shortened and redacted by [`redact_value`](crate::redact::redact_value))
+/// before returning the original error.
+///
+/// The extra round trip only happens on a decode error, but it does execute the query a second
+/// time, so only use this for statements without side effects.
+///
+/// `params` are the values bound in `args`, only used to record the query with the
+/// `query-recorder` feature; `args` itself is already encoded and cannot be read back.
+pub async fn fetch_all_diag<T>(
+    sql: &str,
+    args: MySqlArguments,
+    params: &[&dyn std::fmt::Debug],
+) -> Result<Vec<T>, sqlx::Error>
+where
+    T: for<'r> FromRow<'r, MySqlRow> + Send + Unpin,
+{
+    #[cfg(not(feature = "query-recorder"))]
+    let _ = params;
+    #[cfg(feature = "diagnostics")]
+    let retry_args = args.clone();
+
+    crate::statement_cache::track(sql);
+    #[cfg(feature = "query-recorder")]
+    let started = std::time::Instant::now();
+
+    let result = sqlx::query_as_with::<_, T, _>(sql, args)
+        .fetch_all(get_db_pool())
+        .await;
+
+    #[cfg(feature = "query-recorder")]
+    if let Ok(rows) = &result {
+        crate::recorder::record_query(sql, params, started.elapsed(), rows.len() as u64);
+    }
+
+    #[cfg(feature = "diagnostics")]
+    if let Err(sqlx::Error::ColumnDecode { index, source }) = &result {
+        log_decode_diagnostics::<T>(sql, retry_args, index, &**source).await;
+    }
+
+    result
+}
+
+/// Re-runs the query untyped and logs the column that failed to decode, with the value of the
+/// first row that `T` cannot be decoded from.
+#[cfg(feature = "diagnostics")]
+async fn log_decode_diagnostics<T>(
+    sql: &str,
+    args: MySqlArguments,
+    index: &str,
+    source: &(dyn std::error::Error + Send + Sync),
+) where
+    T: for<'r> FromRow<'r, MySqlRow>,
+{
+    use crate::logging::log_error;
+    use sqlx::{Column, Row, TypeInfo};
+
+    let rows = match sqlx::query_with(sql, args).fetch_all(get_db_pool()).await {
+        Ok(rows) => rows,
+        Err(e) => {
+            log_error(format_args!(
+                "Failed to re-run query for decode diagnostics: {}",
+                crate::redact::format_db_error(&e)
+            ));
+            return;
+        }
+    };
+
+    // sqlx reports the column index as it was passed to `try_get`: either a position or a
+    // (debug-formatted, hence quoted) column name.
+    let index = index.trim_matches('"');
+    let Some(column) = rows.first().and_then(|row| {
+        row.columns()
+            .iter()
+            .find(|c| c.name() == index || c.ordinal().to_string() == index)
+    }) else {
+        log_error(format_args!(
+            "Failed to decode column {} ({}); the untyped query returned no such column.",
+            index, source
+        ));
+        return;
+    };
+
+    // Only the first failing row is reported, however many rows the query returns.
+    let row = rows
+        .iter()
+        .find(|row| matches!(T::from_row(row), Err(sqlx::Error::ColumnDecode { .. })))
+        .unwrap_or(&rows[0]);
+    let value = crate::row::column_to_json(row, column.ordinal());
+    log_error(format_args!(
+        "{}",
+        format_decode_diagnostic(column.name(), column.type_info().name(), &value, source)
+    ));
+}
+
+/// Formats the diagnostic line logged for a single value that failed to decode.
+#[cfg(feature = "diagnostics")]
+fn format_decode_diagnostic(
+    column: &str,
+    type_name: &str,
+    value: &serde_json::Value,
+    source: &(dyn std::error::Error + Send + Sync),
+) -> String {
+    format!(
+        "Failed to decode column `{}` (MySQL type {}): raw value {} ({})",
+        column,
+        type_name,
+        crate::redact::redact_value(value),
+        source
+    )
+}
+
+#[cfg(all(test, feature = "diagnostics"))]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_decode_diagnostic_names_the_column() {
+        let source: Box<dyn std::error::Error + Send + Sync> =
+            "mismatched types; Rust type `i64` is not compatible with SQL type `VARCHAR`".into();
+        let line = format_decode_diagnostic("age", "VARCHAR", &json!("forty-two"), &*source);
+
+        assert!(line.contains("`age`"));
+        assert!(line.contains("VARCHAR"));
+        assert!(line.contains("\"forty-two\""));
+
+        let line = format_decode_diagnostic("bio", "TEXT", &json!("x".repeat(5000)), &*source);
+        assert!(line.contains("(5002 chars)"));
+        assert!(line.len() < 300, "{}", line);
+    }
+
+    #[test]
+    fn test_decode_failure_logs_only_the_first_bad_row() {
+        crate::test_util::run_with_db(|_| async {
+            let sql = "SELECT CAST(v AS CHAR) AS age FROM \
+                       (SELECT '1' AS v UNION ALL SELECT 'x' UNION ALL SELECT 'y') t";
+            let (buffer, guard) = crate::test_util::start_log_capture();
+            let result: Result<Vec<(i64,)>, _> =
+                fetch_all_diag(sql, MySqlArguments::default(), &[]).await;
+            drop(guard);
+            assert!(result.is_err());
+
+            let logs = buffer.contents();
+            assert_eq!(
+                logs.matches("Failed to decode column").count(),
+                1,
+                "{}",
+                logs
+            );
+            assert!(logs.contains("\"x\""), "{}", logs);
+        });
+    }
+}
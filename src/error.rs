@@ -0,0 +1,115 @@
+use std::fmt;
+
+use crate::transaction::LongTransaction;
+
+/// Errors returned by the helpers of this crate.
+///
+/// Most variants wrap a failure reported by the database, while others describe invalid input
+/// that was rejected before any query was sent.
+#[derive(Debug)]
+pub enum DbError {
+    /// An error returned by sqlx while talking to the database.
+    Sqlx(sqlx::Error),
+    /// A table or column name that is not a plain SQL identifier.
+    InvalidIdentifier(String),
+    /// The `database` configuration is missing a required value or contains an invalid one.
+    Config(String),
+    /// Pagination parameters that are out of range, such as a negative limit or offset.
+    InvalidPagination(String),
+    /// A query that must return exactly one row returned none.
+    NotFound,
+    /// A query that must return exactly one row returned more. `count` is the number of rows
+    /// read before giving up, so it is a lower bound.
+    TooManyRows { count: usize },
+    /// Statement number `index` (starting at 0) of a batch failed, so the batch was rolled back.
+    StatementFailed { index: usize, source: sqlx::Error },
+    /// No connection became available within the acquire timeout. The query was never sent,
+    /// so retrying is safe.
+    AcquireTimeout,
+    /// A query did not finish within its time limit, either client-side or because the server
+    /// aborted it (`max_execution_time`). It may have had effects, so it should not be retried
+    /// blindly.
+    QueryTimeout,
+    /// A DDL statement timed out waiting for a metadata lock on its table. `blockers` are the
+    /// transactions that were open at that point, oldest first; one of them holds the lock and
+    /// has to commit, roll back or be killed before the DDL can run.
+    MetadataLockTimeout {
+        blockers: Vec<LongTransaction>,
+        source: sqlx::Error,
+    },
+    /// The concurrency limit of the query labelled `label` was reached and
+    /// `database.limited_query_fail_fast` is set, so the query was not sent.
+    ConcurrencyLimit { label: String },
+    /// A write was rejected because the server is read-only: a replica, or a primary during a
+    /// failover (MySQL errors `1290` and `1836`, see [`is_read_only`](crate::retry::is_read_only)).
+    ReadOnly { source: sqlx::Error },
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DbError::Sqlx(e) => write!(f, "database error: {}", e),
+            DbError::InvalidIdentifier(ident) => write!(f, "invalid SQL identifier: {:?}", ident),
+            DbError::Config(msg) => write!(f, "invalid database configuration: {}", msg),
+            DbError::InvalidPagination(msg) => write!(f, "invalid pagination: {}", msg),
+            DbError::NotFound => write!(f, "expected exactly one row, found none"),
+            DbError::TooManyRows { count } => {
+                write!(f, "expected exactly one row, found at least {}", count)
+            }
+            DbError::StatementFailed { index, source } => {
+                write!(f, "statement {} failed: {}", index, source)
+            }
+            DbError::AcquireTimeout => write!(f, "timed out waiting for a database connection"),
+            DbError::QueryTimeout => write!(f, "query timed out"),
+            DbError::MetadataLockTimeout { blockers, .. } => {
+                write!(f, "timed out waiting for a metadata lock")?;
+                if blockers.is_empty() {
+                    return write!(f, "; no open transaction found, check SHOW PROCESSLIST");
+                }
+                write!(f, "; finish or KILL the blocking transaction(s):")?;
+                for (i, blocker) in blockers.iter().enumerate() {
+                    let separator = if i == 0 { " " } else { ", " };
+                    write!(
+                        f,
+                        "{}connection {} ({}, open for {}s)",
+                        separator,
+                        blocker.connection_id,
+                        blocker.state,
+                        blocker.age.as_secs()
+                    )?;
+                }
+                Ok(())
+            }
+            DbError::ConcurrencyLimit { label } => {
+                write!(f, "too many concurrent executions of query {:?}", label)
+            }
+            DbError::ReadOnly { source } => {
+                write!(f, "write rejected by a read-only server: {}", source)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DbError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DbError::Sqlx(e)
+            | DbError::StatementFailed { source: e, .. }
+            | DbError::MetadataLockTimeout { source: e, .. }
+            | DbError::ReadOnly { source: e } => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Pool acquire timeouts become [`DbError::AcquireTimeout`] and writes rejected by a read-only
+/// server [`DbError::ReadOnly`]; every other error is wrapped in [`DbError::Sqlx`].
+impl From<sqlx::Error> for DbError {
+    fn from(e: sqlx::Error) -> Self {
+        match e {
+            sqlx::Error::PoolTimedOut => DbError::AcquireTimeout,
+            e if crate::retry::is_read_only(&e) => DbError::ReadOnly { source: e },
+            e => DbError::Sqlx(e),
+        }
+    }
+}
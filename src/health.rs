@@ -0,0 +1,144 @@
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::db::get_db_pool;
+use crate::logging::log_warn;
+use crate::redact::format_db_error;
+
+static HEALTH: OnceLock<HealthTracker> = OnceLock::new();
+
+/// The end of an outage, as reported by [`HealthTracker::record`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Recovery {
+    /// Number of consecutive failed checks.
+    pub failures: u32,
+    /// Time from the first failed check to the successful one.
+    pub outage: Duration,
+}
+
+#[derive(Debug, Default)]
+struct Outage {
+    failures: u32,
+    since: Option<Instant>,
+}
+
+/// Tracks consecutive failed health checks and reports when they end.
+#[derive(Debug, Default)]
+pub struct HealthTracker {
+    outage: Mutex<Outage>,
+}
+
+impl HealthTracker {
+    /// Creates a tracker for a healthy database.
+    pub fn new() -> Self {
+        HealthTracker::default()
+    }
+
+    /// Records the outcome of a health check made at `now`.
+    ///
+    /// When a check succeeds after one or more failures, an `info` event with the message
+    /// `db_recovered` and the fields `failures` and `outage_ms` is emitted and the recovery is
+    /// returned. Other checks return `None`.
+    pub fn record(&self, healthy: bool, now: Instant) -> Option<Recovery> {
+        let mut outage = self.outage.lock().unwrap_or_else(|e| e.into_inner());
+        if !healthy {
+            outage.failures += 1;
+            outage.since.get_or_insert(now);
+            return None;
+        }
+
+        let since = outage.since.take()?;
+        let recovery = Recovery {
+            failures: std::mem::take(&mut outage.failures),
+            outage: now.saturating_duration_since(since),
+        };
+        tracing::info!(
+            failures = recovery.failures,
+            outage_ms = recovery.outage.as_millis() as u64,
+            "db_recovered"
+        );
+        Some(recovery)
+    }
+
+    /// Returns the number of consecutive failed checks so far, `0` if the last check succeeded.
+    pub fn consecutive_failures(&self) -> u32 {
+        self.outage
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .failures
+    }
+}
+
+/// Checks that the global pool can reach the database by running `SELECT 1`.
+///
+/// Outcomes are tracked across calls: once a check succeeds after failed ones, a structured
+/// `info` event `db_recovered` is emitted with the number of failed checks (`failures`) and the
+/// time since the first of them (`outage_ms`), so alerting can resolve an incident. Failed
+/// checks are logged as warnings. Run this periodically, e.g. from a readiness probe.
+///
+/// # Example
+/// ```rust,no_run
+/// use std::time::Duration;
+/// use zirv_db_sqlx::health::check_health;
+///
+/// # async fn example() {
+/// loop {
+///     let _ = check_health().await;
+///     tokio::time::sleep(Duration::from_secs(5)).await;
+/// }
+/// # }
+/// ```
+pub async fn check_health() -> Result<(), sqlx::Error> {
+    let result = sqlx::query("SELECT 1")
+        .execute(get_db_pool())
+        .await
+        .map(|_| ());
+    let tracker = HEALTH.get_or_init(HealthTracker::new);
+    tracker.record(result.is_ok(), Instant::now());
+    if let Err(e) = &result {
+        log_warn(format_args!(
+            "Database health check failed ({} in a row): {}",
+            tracker.consecutive_failures(),
+            format_db_error(e)
+        ));
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{self, capture_logs};
+
+    #[test]
+    fn test_recovery_is_reported_once_with_outage_duration() {
+        let tracker = HealthTracker::new();
+        let start = Instant::now();
+
+        let logs = capture_logs(|| {
+            assert_eq!(tracker.record(true, start), None);
+            assert_eq!(tracker.record(false, start + Duration::from_secs(1)), None);
+            assert_eq!(tracker.record(false, start + Duration::from_secs(2)), None);
+            assert_eq!(tracker.record(false, start + Duration::from_secs(3)), None);
+            assert_eq!(tracker.consecutive_failures(), 3);
+            assert_eq!(
+                tracker.record(true, start + Duration::from_millis(4500)),
+                Some(Recovery {
+                    failures: 3,
+                    outage: Duration::from_millis(3500),
+                })
+            );
+            assert_eq!(tracker.record(true, start + Duration::from_secs(5)), None);
+            assert_eq!(tracker.consecutive_failures(), 0);
+        });
+
+        assert_eq!(logs, "INFO db_recovered failures=3 outage_ms=3500\n");
+    }
+
+    #[test]
+    fn test_check_health_against_database() {
+        test_util::run_with_db(|_| async {
+            check_health().await.unwrap();
+        });
+    }
+}
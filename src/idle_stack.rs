@@ -0,0 +1,242 @@
+use std::ops::{Deref, DerefMut};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use sqlx::pool::PoolConnection;
+use sqlx::{Connection, MySql, MySqlConnection, Pool};
+
+use crate::config::{AcquireOrder, PoolConfig};
+use crate::db::non_zero_secs;
+
+/// The stack in front of the global pool, set up by `init_db_pool` with
+/// `database.acquire_order = "lifo"`.
+static IDLE_STACK: OnceLock<IdleStack<PoolConnection<MySql>>> = OnceLock::new();
+
+/// A connection parked in an [`IdleStack`].
+struct Parked<C> {
+    conn: C,
+    /// When the connection was taken from the pool.
+    from_pool: Instant,
+    /// When the connection was parked.
+    released: Instant,
+}
+
+/// Connections released by the crate's acquire helpers, handed out again most recent first.
+///
+/// sqlx's pool always reuses its longest idle connection. Keeping released connections out of
+/// the pool in a stack lets the most recently used one be reused instead, so under light load
+/// the same few connections take the traffic while the others idle out in the pool.
+pub(crate) struct IdleStack<C> {
+    parked: Mutex<Vec<Parked<C>>>,
+    capacity: usize,
+    idle_timeout: Option<Duration>,
+    max_lifetime: Option<Duration>,
+}
+
+impl<C> IdleStack<C> {
+    /// Creates a stack keeping at most `capacity` connections, each for at most `idle_timeout`
+    /// and never once it has been out of the pool for `max_lifetime`.
+    pub(crate) fn new(
+        capacity: usize,
+        idle_timeout: Option<Duration>,
+        max_lifetime: Option<Duration>,
+    ) -> Self {
+        IdleStack {
+            parked: Mutex::new(Vec::with_capacity(capacity)),
+            capacity,
+            idle_timeout,
+            max_lifetime,
+        }
+    }
+
+    /// Takes the most recently parked connection, with the time it was taken from the pool.
+    ///
+    /// Connections parked for longer than the idle timeout are dropped on the way, which hands
+    /// them back to the pool.
+    fn pop(&self, now: Instant) -> Option<(C, Instant)> {
+        let mut expired = Vec::new();
+        let found = {
+            let mut parked = self.parked.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(idle_timeout) = self.idle_timeout {
+                let fresh = parked
+                    .iter()
+                    .position(|p| now.saturating_duration_since(p.released) < idle_timeout)
+                    .unwrap_or(parked.len());
+                expired.extend(parked.drain(..fresh));
+            }
+            parked.pop()
+        };
+        drop(expired);
+        found.map(|p| (p.conn, p.from_pool))
+    }
+
+    /// Parks a released connection. A connection out of the pool for `max_lifetime` is dropped
+    /// instead, and beyond the capacity the longest parked connection is dropped.
+    fn push(&self, conn: C, from_pool: Instant, now: Instant) {
+        if self
+            .max_lifetime
+            .is_some_and(|lifetime| now.saturating_duration_since(from_pool) >= lifetime)
+        {
+            return;
+        }
+        let evicted = {
+            let mut parked = self.parked.lock().unwrap_or_else(|e| e.into_inner());
+            parked.push(Parked {
+                conn,
+                from_pool,
+                released: now,
+            });
+            (parked.len() > self.capacity).then(|| parked.remove(0))
+        };
+        drop(evicted);
+    }
+}
+
+impl IdleStack<PoolConnection<MySql>> {
+    /// Creates the stack for a pool built from `config`, if it asks for LIFO acquisition.
+    pub(crate) fn for_config(config: &PoolConfig) -> Option<Self> {
+        (config.acquire_order == AcquireOrder::Lifo).then(|| {
+            IdleStack::new(
+                stack_capacity(config.max_connections),
+                non_zero_secs(config.idle_timeout_secs),
+                non_zero_secs(config.max_lifetime_secs),
+            )
+        })
+    }
+}
+
+/// Sets up the stack in front of the global pool if `config` asks for LIFO acquisition.
+pub(crate) fn init(config: &PoolConfig) {
+    if let Some(stack) = IdleStack::for_config(config) {
+        let _ = IDLE_STACK.set(stack);
+    }
+}
+
+/// Returns the stack in front of the global pool, if LIFO acquisition is enabled.
+pub(crate) fn global() -> Option<&'static IdleStack<PoolConnection<MySql>>> {
+    IDLE_STACK.get()
+}
+
+/// The number of connections the stack keeps: half of the pool, so that callers using the pool
+/// directly are never left without connections.
+fn stack_capacity(max_connections: u32) -> usize {
+    (max_connections as usize / 2).max(1)
+}
+
+/// A connection taken by one of the crate's acquire helpers.
+///
+/// Dereferences to the underlying connection. When dropped, it is parked in its stack, if it
+/// came with one, or goes back to the pool.
+pub(crate) struct Acquired {
+    conn: Option<PoolConnection<MySql>>,
+    from_pool: Instant,
+    stack: Option<&'static IdleStack<PoolConnection<MySql>>>,
+}
+
+impl Acquired {
+    /// Detaches the connection from the pool, so it is closed instead of being reused.
+    pub(crate) fn detach(mut self) -> MySqlConnection {
+        self.conn
+            .take()
+            .expect("connection already released")
+            .detach()
+    }
+}
+
+impl Deref for Acquired {
+    type Target = MySqlConnection;
+
+    fn deref(&self) -> &Self::Target {
+        self.conn.as_ref().expect("connection already released")
+    }
+}
+
+impl DerefMut for Acquired {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.conn.as_mut().expect("connection already released")
+    }
+}
+
+impl Drop for Acquired {
+    fn drop(&mut self) {
+        if let (Some(conn), Some(stack)) = (self.conn.take(), self.stack) {
+            stack.push(conn, self.from_pool, Instant::now());
+        }
+    }
+}
+
+/// Takes the most recently parked connection of `stack` if there is a usable one, and a
+/// connection from `pool` otherwise.
+///
+/// Parked connections bypass the pool's health check, so they are pinged first; one that does
+/// not answer is closed.
+pub(crate) async fn acquire_from(
+    pool: &Pool<MySql>,
+    stack: Option<&'static IdleStack<PoolConnection<MySql>>>,
+) -> Result<Acquired, sqlx::Error> {
+    if let Some(stack) = stack {
+        while let Some((mut conn, from_pool)) = stack.pop(Instant::now()) {
+            if conn.ping().await.is_ok() {
+                return Ok(Acquired {
+                    conn: Some(conn),
+                    from_pool,
+                    stack: Some(stack),
+                });
+            }
+            let _ = conn.close().await;
+        }
+    }
+    Ok(Acquired {
+        conn: Some(pool.acquire().await?),
+        from_pool: Instant::now(),
+        stack,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_most_recently_released_connection_comes_first() {
+        let stack = IdleStack::new(3, None, None);
+        let now = Instant::now();
+        for conn in 1..=3 {
+            stack.push(conn, now, now);
+        }
+        assert_eq!(stack.pop(now).map(|(c, _)| c), Some(3));
+        stack.push(3, now, now);
+        assert_eq!(stack.pop(now).map(|(c, _)| c), Some(3));
+        assert_eq!(stack.pop(now).map(|(c, _)| c), Some(2));
+        assert_eq!(stack.pop(now).map(|(c, _)| c), Some(1));
+        assert_eq!(stack.pop(now), None);
+    }
+
+    #[test]
+    fn test_stack_hands_back_old_idle_and_excess_connections() {
+        let start = Instant::now();
+        let stack = IdleStack::new(
+            2,
+            Some(Duration::from_secs(10)),
+            Some(Duration::from_secs(60)),
+        );
+        // Over capacity, the longest parked connection goes back to the pool.
+        for conn in 1..=3 {
+            stack.push(conn, start, start + Duration::from_secs(conn));
+        }
+        // 2 has been parked for 10s by now, 3 for 9s.
+        let later = start + Duration::from_secs(12);
+        assert_eq!(stack.pop(later).map(|(c, _)| c), Some(3));
+        assert_eq!(stack.pop(later), None);
+
+        // A connection out of the pool for its whole lifetime is not parked.
+        stack.push(4, start, start + Duration::from_secs(60));
+        assert_eq!(stack.pop(start + Duration::from_secs(60)), None);
+    }
+
+    #[test]
+    fn test_stack_capacity_is_half_the_pool() {
+        assert_eq!(stack_capacity(10), 5);
+        assert_eq!(stack_capacity(1), 1);
+    }
+}
@@ -0,0 +1,254 @@
+use serde_json::Value;
+use sqlx::mysql::MySqlRow;
+use sqlx::{FromRow, MySqlConnection};
+
+use crate::db::with_pinned;
+use crate::error::DbError;
+use crate::server::{ServerVersion, server_version};
+use crate::sql::{json_arguments, quote_identifier};
+
+/// Keywords that may appear between `INSERT` and the table name.
+const INSERT_MODIFIERS: &[&str] = &["low_priority", "delayed", "high_priority", "ignore", "into"];
+
+/// How [`insert_returning`] gets the inserted row back.
+#[derive(Debug, PartialEq, Eq)]
+enum InsertPlan {
+    /// Run the statement with `RETURNING *` appended.
+    Returning(String),
+    /// Run the statement `sql` as is, then select the row of the quoted `table` with the
+    /// generated id.
+    LastInsertId { sql: String, table: String },
+}
+
+/// Inserts a single row and returns it as stored, including generated and default values.
+///
+/// `sql` is an `INSERT INTO table ...` statement for one row, with `?` placeholders for `binds`
+/// (bound as described in [`json_arguments`]). On servers that support it (MariaDB 10.5 and
+/// later, see [`server_version`]), `RETURNING *` is appended and the row comes back with the
+/// insert. Elsewhere, the statement runs as is and the row is then selected by its
+/// auto-increment id (`LAST_INSERT_ID()`) on the same connection, which requires the table to
+/// have an auto-increment column.
+///
+/// # Errors
+/// - [`DbError::InvalidIdentifier`] if the table name cannot be read from `sql` (only checked
+///   when the row has to be selected separately).
+/// - [`DbError::NotFound`] if no row was inserted (e.g. by `INSERT IGNORE`), or, without
+///   `RETURNING`, if the table has no auto-increment column; the insert itself may have
+///   succeeded then.
+/// - [`DbError::Sqlx`] if a statement fails.
+///
+/// # Example
+/// ```rust,no_run
+/// use serde_json::json;
+/// use zirv_db_sqlx::insert::insert_returning;
+///
+/// #[derive(sqlx::FromRow)]
+/// struct User {
+///     id: u64,
+///     name: String,
+///     created_at: chrono::NaiveDateTime,
+/// }
+///
+/// async fn create_user(name: &str) -> Result<User, zirv_db_sqlx::error::DbError> {
+///     insert_returning("INSERT INTO users (name) VALUES (?)", &[json!(name)]).await
+/// }
+/// ```
+pub async fn insert_returning<T>(sql: &str, binds: &[Value]) -> Result<T, DbError>
+where
+    T: for<'r> FromRow<'r, MySqlRow> + Send + Unpin,
+{
+    let plan = plan_insert(sql, server_version().await?)?;
+    with_pinned(async |conn| run_insert(conn, &plan, binds).await).await
+}
+
+/// Decides how to get the row inserted by `sql` back from a server of `version`.
+fn plan_insert(sql: &str, version: &ServerVersion) -> Result<InsertPlan, DbError> {
+    let sql = sql.trim_end().trim_end_matches(';').trim_end();
+    if version.supports_insert_returning() {
+        return Ok(InsertPlan::Returning(format!("{} RETURNING *", sql)));
+    }
+
+    let table = insert_target(sql).ok_or_else(|| DbError::InvalidIdentifier(sql.to_owned()))?;
+    Ok(InsertPlan::LastInsertId {
+        sql: sql.to_owned(),
+        table: quote_identifier(&table)?,
+    })
+}
+
+/// Reads the (possibly schema-qualified) table name of an `INSERT` statement, without quotes.
+fn insert_target(sql: &str) -> Option<String> {
+    let mut words = sql.split_whitespace();
+    if !words.next()?.eq_ignore_ascii_case("insert") {
+        return None;
+    }
+    let target =
+        words.find(|word| !INSERT_MODIFIERS.contains(&word.to_ascii_lowercase().as_str()))?;
+    let table = target.split('(').next()?.replace('`', "");
+    (!table.is_empty()).then_some(table)
+}
+
+/// Runs the insert according to `plan` and fetches the inserted row.
+async fn run_insert<T>(
+    conn: &mut MySqlConnection,
+    plan: &InsertPlan,
+    binds: &[Value],
+) -> Result<T, DbError>
+where
+    T: for<'r> FromRow<'r, MySqlRow> + Send + Unpin,
+{
+    let args = json_arguments(binds)?;
+    match plan {
+        InsertPlan::Returning(sql) => {
+            crate::statement_cache::track(sql);
+            #[cfg(feature = "query-recorder")]
+            let started = std::time::Instant::now();
+            let row = sqlx::query_as_with(sql, args)
+                .fetch_optional(&mut *conn)
+                .await?;
+            #[cfg(feature = "query-recorder")]
+            crate::recorder::record_values(sql, binds, started, row.is_some() as u64);
+            row.ok_or(DbError::NotFound)
+        }
+        InsertPlan::LastInsertId { sql, table } => {
+            crate::statement_cache::track(sql);
+            #[cfg(feature = "query-recorder")]
+            let started = std::time::Instant::now();
+            let result = sqlx::query_with(sql, args).execute(&mut *conn).await?;
+            #[cfg(feature = "query-recorder")]
+            crate::recorder::record_values(sql, binds, started, result.rows_affected());
+            if result.rows_affected() == 0 {
+                return Err(DbError::NotFound);
+            }
+            let Some(id_column) = auto_increment_column(conn, table).await? else {
+                return Err(DbError::NotFound);
+            };
+            let select = format!(
+                "SELECT * FROM {} WHERE {} = ?",
+                table,
+                quote_identifier(&id_column)?
+            );
+            sqlx::query_as(&select)
+                .bind(result.last_insert_id())
+                .fetch_optional(&mut *conn)
+                .await?
+                .ok_or(DbError::NotFound)
+        }
+    }
+}
+
+/// Returns the name of the auto-increment column of the quoted `table`, if it has one.
+async fn auto_increment_column(
+    conn: &mut MySqlConnection,
+    table: &str,
+) -> Result<Option<String>, sqlx::Error> {
+    let unquoted = table.replace('`', "");
+    let (schema, table) = match unquoted.split_once('.') {
+        Some((schema, table)) => (Some(schema), table),
+        None => (None, unquoted.as_str()),
+    };
+    sqlx::query_scalar(
+        "SELECT CAST(COLUMN_NAME AS CHAR) FROM information_schema.columns \
+         WHERE TABLE_SCHEMA = COALESCE(?, DATABASE()) AND TABLE_NAME = ? \
+           AND EXTRA LIKE '%auto_increment%'",
+    )
+    .bind(schema)
+    .bind(table)
+    .fetch_optional(conn)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+    use serde_json::json;
+
+    fn version(version: &str) -> ServerVersion {
+        ServerVersion::parse(version).unwrap()
+    }
+
+    #[test]
+    fn test_plan_uses_returning_on_mariadb() {
+        assert_eq!(
+            plan_insert(
+                "INSERT INTO users (name) VALUES (?);",
+                &version("10.6.16-MariaDB")
+            )
+            .unwrap(),
+            InsertPlan::Returning("INSERT INTO users (name) VALUES (?) RETURNING *".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_plan_falls_back_to_last_insert_id() {
+        for server in ["8.0.36", "10.4.32-MariaDB"] {
+            assert_eq!(
+                plan_insert(
+                    "INSERT IGNORE INTO app.users(name) VALUES (?)",
+                    &version(server)
+                )
+                .unwrap(),
+                InsertPlan::LastInsertId {
+                    sql: "INSERT IGNORE INTO app.users(name) VALUES (?)".to_owned(),
+                    table: "`app`.`users`".to_owned(),
+                }
+            );
+        }
+        assert_eq!(
+            insert_target("insert into `users` (name) values (?)").as_deref(),
+            Some("users")
+        );
+    }
+
+    #[test]
+    fn test_plan_rejects_unreadable_targets() {
+        let mysql = version("8.0.36");
+        assert!(matches!(
+            plan_insert("UPDATE users SET name = ?", &mysql),
+            Err(DbError::InvalidIdentifier(_))
+        ));
+        assert!(matches!(
+            plan_insert("INSERT INTO users;DROP TABLE users VALUES (?)", &mysql),
+            Err(DbError::InvalidIdentifier(_))
+        ));
+    }
+
+    #[test]
+    fn test_insert_returning_against_database() {
+        test_util::run_with_db(|pool| async move {
+            sqlx::query("DROP TABLE IF EXISTS zirv_test_returning")
+                .execute(pool)
+                .await
+                .unwrap();
+            sqlx::query(
+                "CREATE TABLE zirv_test_returning (\
+                   id BIGINT UNSIGNED AUTO_INCREMENT PRIMARY KEY, \
+                   name VARCHAR(16) NOT NULL, \
+                   status VARCHAR(16) NOT NULL DEFAULT 'new'\
+                 )",
+            )
+            .execute(pool)
+            .await
+            .unwrap();
+
+            let sql = "INSERT INTO zirv_test_returning (name) VALUES (?)";
+            let first: (u64, String, String) =
+                insert_returning(sql, &[json!("first")]).await.unwrap();
+            assert_eq!(first, (1, "first".to_owned(), "new".to_owned()));
+
+            // Whatever the server, the fallback path works as well.
+            let plan = plan_insert(sql, &version("8.0.36")).unwrap();
+            let mut conn = pool.acquire().await.unwrap();
+            let second: (u64, String, String) = run_insert(&mut conn, &plan, &[json!("second")])
+                .await
+                .unwrap();
+            assert_eq!(second, (2, "second".to_owned(), "new".to_owned()));
+            drop(conn);
+
+            sqlx::query("DROP TABLE zirv_test_returning")
+                .execute(pool)
+                .await
+                .unwrap();
+        });
+    }
+}
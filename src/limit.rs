@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use tokio::sync::Semaphore;
+use zirv_config::read_config;
+
+use crate::error::DbError;
+use crate::logging::log_warn;
+
+static QUERY_LIMITS: OnceLock<QueryLimits> = OnceLock::new();
+
+static FAIL_FAST: OnceLock<bool> = OnceLock::new();
+
+/// One semaphore per query label, created on first use, with the limit it was created with.
+#[derive(Default)]
+struct QueryLimits {
+    semaphores: Mutex<HashMap<String, (Arc<Semaphore>, usize)>>,
+}
+
+impl QueryLimits {
+    /// Returns the semaphore of `label`, creating it with `max_concurrent` permits.
+    ///
+    /// A label keeps the limit it was created with; a different `max_concurrent` is logged and
+    /// ignored.
+    fn semaphore(&self, label: &str, max_concurrent: usize) -> Result<Arc<Semaphore>, DbError> {
+        if max_concurrent == 0 {
+            return Err(DbError::Config(format!(
+                "the concurrency limit of query label {:?} must be at least 1",
+                label
+            )));
+        }
+        let mut semaphores = self.semaphores.lock().unwrap_or_else(|e| e.into_inner());
+        let (semaphore, limit) = semaphores
+            .entry(label.to_owned())
+            .or_insert_with(|| (Arc::new(Semaphore::new(max_concurrent)), max_concurrent));
+        if *limit != max_concurrent {
+            log_warn(format_args!(
+                "Query label {:?} is limited to {} concurrent queries; ignoring the limit of {}",
+                label, limit, max_concurrent
+            ));
+        }
+        Ok(semaphore.clone())
+    }
+
+    /// Runs `query` once fewer than `max_concurrent` other queries of `label` are running.
+    async fn run<Fut, T, E>(
+        &self,
+        label: &str,
+        max_concurrent: usize,
+        fail_fast: bool,
+        query: Fut,
+    ) -> Result<T, DbError>
+    where
+        Fut: Future<Output = Result<T, E>>,
+        E: Into<DbError>,
+    {
+        let semaphore = self.semaphore(label, max_concurrent)?;
+        let busy = || DbError::ConcurrencyLimit {
+            label: label.to_owned(),
+        };
+        let _permit = if fail_fast {
+            semaphore.try_acquire().map_err(|_| busy())?
+        } else {
+            // The semaphore is never closed.
+            semaphore.acquire().await.map_err(|_| busy())?
+        };
+        query.await.map_err(Into::into)
+    }
+}
+
+/// Runs `query` with at most `max_concurrent` queries of the same `label` running at once.
+///
+/// Every label has its own semaphore, created on first use with that call's `max_concurrent`;
+/// the limit of a label cannot change afterwards, so use the same value everywhere (a different
+/// one is logged as a warning and ignored). Callers
+/// over the limit wait for a running query of the label to finish. With
+/// `database.limited_query_fail_fast` set to `true`, they fail immediately with
+/// [`DbError::ConcurrencyLimit`] instead, e.g. to answer "try again later" rather than queue up
+/// behind an expensive report.
+///
+/// The limit is per process; a fleet of `n` processes runs up to `n * max_concurrent` queries
+/// of a label.
+///
+/// # Errors
+/// Returns [`DbError::Config`] if `max_concurrent` is `0`, [`DbError::ConcurrencyLimit`] if the
+/// limit is reached in fail-fast mode, and the error of `query` otherwise.
+///
+/// See also the [`limited_query!`](crate::limited_query) macro.
+///
+/// # Example
+/// ```rust,no_run
+/// use zirv_db_sqlx::{db::get_db_pool, limit::run_limited};
+///
+/// async fn heavy_report() -> Result<Vec<(String, i64)>, zirv_db_sqlx::error::DbError> {
+///     run_limited(
+///         "heavy_report",
+///         2,
+///         sqlx::query_as("SELECT region, SUM(total) FROM orders GROUP BY region")
+///             .fetch_all(get_db_pool()),
+///     )
+///     .await
+/// }
+/// ```
+pub async fn run_limited<Fut, T, E>(
+    label: &str,
+    max_concurrent: usize,
+    query: Fut,
+) -> Result<T, DbError>
+where
+    Fut: Future<Output = Result<T, E>>,
+    E: Into<DbError>,
+{
+    let fail_fast = *FAIL_FAST
+        .get_or_init(|| read_config!("database.limited_query_fail_fast", bool).unwrap_or(false));
+    QUERY_LIMITS
+        .get_or_init(QueryLimits::default)
+        .run(label, max_concurrent, fail_fast, query)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_executions_are_capped() {
+        let limits = Arc::new(QueryLimits::default());
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+
+        let tasks: Vec<_> = (0..12)
+            .map(|_| {
+                let (limits, running, peak) = (limits.clone(), running.clone(), peak.clone());
+                tokio::spawn(async move {
+                    limits
+                        .run("heavy_report", 3, false, async {
+                            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                            peak.fetch_max(now, Ordering::SeqCst);
+                            tokio::time::sleep(Duration::from_millis(20)).await;
+                            running.fetch_sub(1, Ordering::SeqCst);
+                            Ok::<_, sqlx::Error>(())
+                        })
+                        .await
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+
+        assert_eq!(peak.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_fail_fast_rejects_queries_over_the_limit() {
+        let limits = QueryLimits::default();
+        let held = limits
+            .semaphore("export", 1)
+            .unwrap()
+            .try_acquire_owned()
+            .unwrap();
+
+        let result = limits
+            .run("export", 1, true, async { Ok::<_, sqlx::Error>(()) })
+            .await;
+        assert!(matches!(
+            result,
+            Err(DbError::ConcurrencyLimit { label }) if label == "export"
+        ));
+
+        // Other labels are not affected, and the label runs again once the permit is back.
+        limits
+            .run("other", 1, true, async { Ok::<_, sqlx::Error>(()) })
+            .await
+            .unwrap();
+        drop(held);
+        limits
+            .run("export", 1, true, async { Ok::<_, sqlx::Error>(()) })
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_zero_limit_is_rejected_and_changed_limits_are_logged() {
+        let limits = QueryLimits::default();
+        let result = limits
+            .run("report", 0, false, async { Ok::<_, sqlx::Error>(()) })
+            .await;
+        assert!(matches!(result, Err(DbError::Config(_))));
+
+        limits.semaphore("report", 2).unwrap();
+        let logs = crate::test_util::capture_logs(|| {
+            let semaphore = limits.semaphore("report", 5).unwrap();
+            assert_eq!(semaphore.available_permits(), 2);
+        });
+        assert_eq!(
+            logs,
+            "WARN Query label \"report\" is limited to 2 concurrent queries; ignoring the limit of 5\n"
+        );
+    }
+}
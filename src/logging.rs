@@ -0,0 +1,167 @@
+use std::fmt;
+use std::future::Future;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use tracing::Level;
+use zirv_config::read_config;
+
+/// Default level for the log line emitted when a transaction is rolled back.
+const DEFAULT_ROLLBACK_LOG_LEVEL: Level = Level::DEBUG;
+
+static ROLLBACK_LOG_LEVEL: OnceLock<Level> = OnceLock::new();
+
+tokio::task_local! {
+    static CORRELATION_ID: String;
+}
+
+/// Runs `fut` with `id` as the correlation id of every log line the crate emits inside it.
+///
+/// This covers error logs as well as warnings, such as the slow-query warning of
+/// [`with_query_timeout`](crate::timeout::with_query_timeout).
+///
+/// The id is task-local: it applies to `fut` and everything it awaits, but not to tasks spawned
+/// from it.
+///
+/// # Example
+/// ```rust,no_run
+/// use zirv_db_sqlx::{commit_transaction, logging::with_correlation_id, start_transaction};
+///
+/// async fn handle_request(request_id: String) -> Result<(), sqlx::Error> {
+///     with_correlation_id(request_id, async {
+///         let mut tx = start_transaction!();
+///         // A failure here is logged with the request id attached.
+///         commit_transaction!(tx);
+///         Ok(())
+///     })
+///     .await
+/// }
+/// ```
+pub async fn with_correlation_id<F: Future>(id: impl Into<String>, fut: F) -> F::Output {
+    CORRELATION_ID.scope(id.into(), fut).await
+}
+
+/// Returns the correlation id of the current task, if one is set.
+pub fn correlation_id() -> Option<String> {
+    CORRELATION_ID.try_with(|id| id.clone()).ok()
+}
+
+/// Formats a log line, prefixed with the current correlation id if there is one.
+#[doc(hidden)]
+pub fn format_log(args: fmt::Arguments<'_>) -> String {
+    match correlation_id() {
+        Some(id) => format!("[correlation_id={}] {}", id, args),
+        None => args.to_string(),
+    }
+}
+
+/// Logs a line at `level` through `tracing`. Used by the crate's macros and helpers.
+#[doc(hidden)]
+pub fn log_at(level: Level, args: fmt::Arguments<'_>) {
+    let line = format_log(args);
+    match level {
+        Level::ERROR => tracing::error!("{}", line),
+        Level::WARN => tracing::warn!("{}", line),
+        Level::INFO => tracing::info!("{}", line),
+        Level::DEBUG => tracing::debug!("{}", line),
+        Level::TRACE => tracing::trace!("{}", line),
+    }
+}
+
+/// Logs an error line. Used by the crate's macros and helpers.
+#[doc(hidden)]
+pub fn log_error(args: fmt::Arguments<'_>) {
+    log_at(Level::ERROR, args);
+}
+
+/// Logs a warning line. Used by the crate's macros and helpers.
+#[doc(hidden)]
+pub fn log_warn(args: fmt::Arguments<'_>) {
+    log_at(Level::WARN, args);
+}
+
+/// Logs that a transaction was rolled back, at `database.rollback_log_level`.
+///
+/// Used by `rollback_transaction!`.
+#[doc(hidden)]
+pub fn log_rollback() {
+    let level = *ROLLBACK_LOG_LEVEL.get_or_init(|| {
+        parse_level(read_config!("database.rollback_log_level", String).as_deref())
+    });
+    log_at(level, format_args!("Transaction rolled back"));
+}
+
+/// Parses a level name (`trace`, `debug`, `info`, `warn` or `error`), falling back to the
+/// default rollback level for missing or unknown names.
+fn parse_level(name: Option<&str>) -> Level {
+    match name.map(Level::from_str) {
+        None => DEFAULT_ROLLBACK_LOG_LEVEL,
+        Some(Ok(level)) => level,
+        Some(Err(_)) => {
+            log_warn(format_args!(
+                "Unknown database.rollback_log_level {:?}, using {}",
+                name.unwrap_or_default(),
+                DEFAULT_ROLLBACK_LOG_LEVEL
+            ));
+            DEFAULT_ROLLBACK_LOG_LEVEL
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::capture_logs;
+
+    #[test]
+    fn test_rollback_log_level_is_configurable() {
+        assert_eq!(parse_level(None), Level::DEBUG);
+        assert_eq!(parse_level(Some("warn")), Level::WARN);
+        assert_eq!(parse_level(Some("ERROR")), Level::ERROR);
+        assert_eq!(parse_level(Some("loud")), Level::DEBUG);
+    }
+
+    #[test]
+    fn test_log_is_emitted_at_the_given_level() {
+        let logs = capture_logs(|| {
+            log_at(parse_level(None), format_args!("Transaction rolled back"));
+            log_at(
+                parse_level(Some("error")),
+                format_args!("Transaction rolled back"),
+            );
+        });
+        assert_eq!(
+            logs,
+            "DEBUG Transaction rolled back\nERROR Transaction rolled back\n"
+        );
+    }
+
+    #[test]
+    fn test_default_rollback_log_is_debug() {
+        // The test configuration does not set database.rollback_log_level.
+        assert_eq!(
+            capture_logs(log_rollback),
+            "DEBUG Transaction rolled back\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_error_log_includes_correlation_id() {
+        let line = with_correlation_id("req-42", async {
+            let e = sqlx::Error::PoolTimedOut;
+            format_log(format_args!("Failed to start transaction: {:?}", e))
+        })
+        .await;
+
+        assert_eq!(
+            line,
+            "[correlation_id=req-42] Failed to start transaction: PoolTimedOut"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_log_without_correlation_id_is_unchanged() {
+        assert_eq!(correlation_id(), None);
+        assert_eq!(format_log(format_args!("plain")), "plain");
+    }
+}
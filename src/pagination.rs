@@ -0,0 +1,126 @@
+use serde_json::Value;
+use sqlx::mysql::MySqlRow;
+use sqlx::{Arguments, FromRow};
+use zirv_config::read_config;
+
+use crate::{db::get_db_pool, error::DbError, sql::json_arguments};
+
+/// Default upper bound for the number of rows in a page.
+const DEFAULT_MAX_PAGE_SIZE: u32 = 100;
+
+/// Validates and clamps untrusted `LIMIT`/`OFFSET` values.
+///
+/// The limit is clamped to `database.max_page_size` (default 100), so a client cannot request
+/// arbitrarily large pages.
+///
+/// # Errors
+/// Returns [`DbError::InvalidPagination`] if `limit` or `offset` is negative.
+///
+/// # Example
+/// ```rust
+/// use zirv_db_sqlx::pagination::safe_limit_offset;
+///
+/// assert_eq!(safe_limit_offset(1_000_000, 20).unwrap(), (100, 20));
+/// assert!(safe_limit_offset(-1, 0).is_err());
+/// ```
+pub fn safe_limit_offset(limit: i64, offset: i64) -> Result<(u32, u64), DbError> {
+    let max_page_size =
+        read_config!("database.max_page_size", u32).unwrap_or(DEFAULT_MAX_PAGE_SIZE);
+    clamp_limit_offset(limit, offset, max_page_size)
+}
+
+fn clamp_limit_offset(limit: i64, offset: i64, max_page_size: u32) -> Result<(u32, u64), DbError> {
+    if limit < 0 || offset < 0 {
+        return Err(DbError::InvalidPagination(format!(
+            "limit and offset must not be negative (limit {}, offset {})",
+            limit, offset
+        )));
+    }
+
+    let limit = u32::try_from(limit).unwrap_or(u32::MAX).min(max_page_size);
+    Ok((limit, offset as u64))
+}
+
+/// Fetches one page of a query's results.
+///
+/// Appends `LIMIT ? OFFSET ?` to `sql`, with the values passed through [`safe_limit_offset`].
+/// `binds` are bound to the placeholders in `sql` before the pagination values.
+///
+/// # Example
+/// ```rust,no_run
+/// use serde_json::json;
+/// use zirv_db_sqlx::pagination::fetch_page;
+///
+/// #[derive(sqlx::FromRow)]
+/// struct User {
+///     id: i64,
+///     name: String,
+/// }
+///
+/// async fn list_users(limit: i64, offset: i64) -> Result<Vec<User>, zirv_db_sqlx::error::DbError> {
+///     fetch_page("SELECT id, name FROM users WHERE active = ? ORDER BY id", &[json!(true)], limit, offset).await
+/// }
+/// ```
+pub async fn fetch_page<T>(
+    sql: &str,
+    binds: &[Value],
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<T>, DbError>
+where
+    T: for<'r> FromRow<'r, MySqlRow> + Send + Unpin,
+{
+    let (limit, offset) = safe_limit_offset(limit, offset)?;
+
+    let mut args = json_arguments(binds)?;
+    args.add(limit).map_err(sqlx::Error::Encode)?;
+    args.add(offset).map_err(sqlx::Error::Encode)?;
+
+    let sql = format!("{} LIMIT ? OFFSET ?", sql);
+    crate::statement_cache::track(&sql);
+    #[cfg(feature = "query-recorder")]
+    let started = std::time::Instant::now();
+    let rows: Vec<T> = sqlx::query_as_with(&sql, args)
+        .fetch_all(get_db_pool())
+        .await?;
+
+    #[cfg(feature = "query-recorder")]
+    {
+        let mut params = binds.to_vec();
+        params.extend([Value::from(limit), Value::from(offset)]);
+        crate::recorder::record_values(&sql, &params, started, rows.len() as u64);
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_oversized_limit_is_clamped() {
+        assert_eq!(clamp_limit_offset(1_000_000, 0, 100).unwrap(), (100, 0));
+        assert_eq!(clamp_limit_offset(i64::MAX, 5, 50).unwrap(), (50, 5));
+        assert_eq!(clamp_limit_offset(25, 10, 100).unwrap(), (25, 10));
+    }
+
+    #[test]
+    fn test_negative_values_are_rejected() {
+        assert!(matches!(
+            clamp_limit_offset(-1, 0, 100),
+            Err(DbError::InvalidPagination(_))
+        ));
+        assert!(matches!(
+            clamp_limit_offset(10, -5, 100),
+            Err(DbError::InvalidPagination(_))
+        ));
+    }
+
+    #[test]
+    fn test_large_offsets_are_kept() {
+        assert_eq!(
+            clamp_limit_offset(10, i64::MAX, 100).unwrap(),
+            (10, i64::MAX as u64)
+        );
+    }
+}
@@ -0,0 +1,258 @@
+use std::fmt;
+use std::time::Duration;
+
+use sqlx::mysql::{MySqlConnectOptions, MySqlSslMode};
+use sqlx::{ConnectOptions, Connection};
+use tokio::net::{TcpStream, lookup_host};
+use tokio::time::timeout;
+
+use crate::config::resolve_pool_config;
+use crate::db::connect_options;
+
+/// A step of the connection process checked by [`diagnose_connection`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Resolving the `database` configuration and parsing the URL.
+    Config,
+    /// Resolving the host name to addresses.
+    Dns,
+    /// Opening a TCP connection to the port.
+    Tcp,
+    /// Negotiating TLS, according to the effective `ssl_mode`.
+    Tls,
+    /// Logging in to MySQL with the configured credentials.
+    Auth,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Stage::Config => "config",
+            Stage::Dns => "dns",
+            Stage::Tcp => "tcp",
+            Stage::Tls => "tls",
+            Stage::Auth => "auth",
+        })
+    }
+}
+
+/// The result of a single [`Stage`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StageOutcome {
+    /// The stage succeeded.
+    Passed,
+    /// The stage failed, with the reason.
+    Failed(String),
+    /// The stage was not run, because an earlier one failed or it does not apply (for example
+    /// TLS with `ssl_mode = disabled`).
+    Skipped,
+}
+
+/// The outcome of every stage checked by [`diagnose_connection`], in order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiagnosticReport {
+    /// One entry per stage, from `Config` to `Auth`.
+    pub stages: Vec<(Stage, StageOutcome)>,
+}
+
+impl DiagnosticReport {
+    /// Returns the first stage that failed, if any.
+    pub fn failed_stage(&self) -> Option<Stage> {
+        self.stages
+            .iter()
+            .find(|(_, outcome)| matches!(outcome, StageOutcome::Failed(_)))
+            .map(|(stage, _)| *stage)
+    }
+
+    /// Returns `true` if no stage failed.
+    pub fn is_ok(&self) -> bool {
+        self.failed_stage().is_none()
+    }
+
+    fn record(&mut self, stage: Stage, outcome: StageOutcome) {
+        self.stages.push((stage, outcome));
+    }
+
+    /// Lists the stages in order, marking those not recorded as skipped.
+    fn finish(mut self) -> Self {
+        self.stages = [
+            Stage::Config,
+            Stage::Dns,
+            Stage::Tcp,
+            Stage::Tls,
+            Stage::Auth,
+        ]
+        .into_iter()
+        .map(|stage| {
+            let outcome = self
+                .stages
+                .iter()
+                .find(|(s, _)| *s == stage)
+                .map_or(StageOutcome::Skipped, |(_, outcome)| outcome.clone());
+            (stage, outcome)
+        })
+        .collect();
+        self
+    }
+}
+
+impl fmt::Display for DiagnosticReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (stage, outcome) in &self.stages {
+            match outcome {
+                StageOutcome::Passed => writeln!(f, "{}: ok", stage)?,
+                StageOutcome::Failed(reason) => writeln!(f, "{}: FAILED ({})", stage, reason)?,
+                StageOutcome::Skipped => writeln!(f, "{}: skipped", stage)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Checks step by step whether the configured database can be reached.
+///
+/// The configuration is resolved the same way as by `init_db_pool`, then the host is resolved,
+/// a TCP connection is opened, and a MySQL connection is established to check TLS and the
+/// credentials. The first stage that fails is reported with its reason and the following ones
+/// are skipped, which turns a cryptic "connection refused" into "the port is closed" or "the
+/// password is wrong". Each network stage is bounded by `database.acquire_timeout_secs`.
+///
+/// The global pool is neither needed nor touched, so this can be called after `init_db_pool`
+/// failed.
+///
+/// # Example
+/// ```rust,no_run
+/// use zirv_db_sqlx::preflight::diagnose_connection;
+///
+/// # async fn example() {
+/// let report = diagnose_connection().await;
+/// if !report.is_ok() {
+///     eprintln!("Database unreachable:\n{}", report);
+/// }
+/// # }
+/// ```
+pub async fn diagnose_connection() -> DiagnosticReport {
+    let mut report = DiagnosticReport { stages: Vec::new() };
+
+    let resolved = resolve_pool_config().and_then(|config| {
+        let options = connect_options(&config, &config.url)?;
+        Ok((options, Duration::from_secs(config.acquire_timeout_secs)))
+    });
+    match resolved {
+        Ok((options, limit)) => {
+            report.record(Stage::Config, StageOutcome::Passed);
+            diagnose(&options, limit, report).await
+        }
+        Err(e) => {
+            report.record(Stage::Config, StageOutcome::Failed(e.to_string()));
+            report.finish()
+        }
+    }
+}
+
+/// Runs the network stages against `options`, each bounded by `limit`.
+async fn diagnose(
+    options: &MySqlConnectOptions,
+    limit: Duration,
+    mut report: DiagnosticReport,
+) -> DiagnosticReport {
+    if options.get_socket().is_none() {
+        let host = options.get_host();
+        let port = options.get_port();
+
+        let addrs = match timeout(limit, lookup_host((host, port))).await {
+            Ok(Ok(addrs)) => addrs.collect::<Vec<_>>(),
+            Ok(Err(e)) => return failed(report, Stage::Dns, e.to_string()),
+            Err(_) => return failed(report, Stage::Dns, timed_out(limit)),
+        };
+        if addrs.is_empty() {
+            let reason = format!("{} did not resolve to any address", host);
+            return failed(report, Stage::Dns, reason);
+        }
+        report.record(Stage::Dns, StageOutcome::Passed);
+
+        match timeout(limit, TcpStream::connect(addrs.as_slice())).await {
+            Ok(Ok(_)) => report.record(Stage::Tcp, StageOutcome::Passed),
+            Ok(Err(e)) => return failed(report, Stage::Tcp, format!("{}:{}: {}", host, port, e)),
+            Err(_) => return failed(report, Stage::Tcp, timed_out(limit)),
+        }
+    }
+
+    match timeout(limit, options.connect()).await {
+        Ok(Ok(conn)) => {
+            // Closing is best effort; the login already succeeded.
+            let _ = conn.close().await;
+            report.record(Stage::Tls, tls_passed(options));
+            report.record(Stage::Auth, StageOutcome::Passed);
+        }
+        Ok(Err(sqlx::Error::Tls(e))) => return failed(report, Stage::Tls, e.to_string()),
+        Ok(Err(e)) => {
+            report.record(Stage::Tls, tls_passed(options));
+            report.record(Stage::Auth, StageOutcome::Failed(e.to_string()));
+        }
+        Err(_) => return failed(report, Stage::Auth, timed_out(limit)),
+    }
+    report.finish()
+}
+
+/// Records `stage` as failed and skips the remaining stages.
+fn failed(mut report: DiagnosticReport, stage: Stage, reason: String) -> DiagnosticReport {
+    report.record(stage, StageOutcome::Failed(reason));
+    report.finish()
+}
+
+/// The TLS outcome once the handshake got past TLS negotiation.
+fn tls_passed(options: &MySqlConnectOptions) -> StageOutcome {
+    match options.get_ssl_mode() {
+        MySqlSslMode::Disabled => StageOutcome::Skipped,
+        _ => StageOutcome::Passed,
+    }
+}
+
+fn timed_out(limit: Duration) -> String {
+    format!("timed out after {:?}", limit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    fn empty_report() -> DiagnosticReport {
+        DiagnosticReport { stages: Vec::new() }
+    }
+
+    #[tokio::test]
+    async fn test_closed_port_fails_at_tcp_stage() {
+        // Bind and immediately release a port so nothing is listening on it.
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let options = MySqlConnectOptions::new().host("127.0.0.1").port(port);
+
+        let report = diagnose(&options, Duration::from_secs(5), empty_report()).await;
+
+        assert_eq!(report.failed_stage(), Some(Stage::Tcp));
+        assert!(!report.is_ok());
+        assert_eq!(report.stages[1], (Stage::Dns, StageOutcome::Passed));
+        assert!(matches!(
+            report.stages[2],
+            (Stage::Tcp, StageOutcome::Failed(_))
+        ));
+        assert_eq!(report.stages[3], (Stage::Tls, StageOutcome::Skipped));
+        assert_eq!(report.stages[4], (Stage::Auth, StageOutcome::Skipped));
+        assert!(report.to_string().contains("tcp: FAILED (127.0.0.1:"));
+    }
+
+    #[test]
+    fn test_report_lists_remaining_stages_as_skipped() {
+        let report = failed(empty_report(), Stage::Config, "no URL".to_owned());
+        assert_eq!(report.failed_stage(), Some(Stage::Config));
+        assert_eq!(
+            report.to_string(),
+            "config: FAILED (no URL)\ndns: skipped\ntcp: skipped\ntls: skipped\nauth: skipped\n"
+        );
+    }
+}
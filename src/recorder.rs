@@ -0,0 +1,279 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+use zirv_config::read_config;
+
+/// Default number of queries kept by the recorder.
+const DEFAULT_CAPACITY: usize = 100;
+
+/// Placeholder recorded instead of a bound value when parameter redaction is enabled.
+pub const REDACTED: &str = "<redacted>";
+
+static RECORDER: OnceLock<QueryRecorder> = OnceLock::new();
+
+/// A single recorded query execution.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryRecord {
+    /// The SQL that was executed.
+    pub sql: String,
+    /// The bound parameters, debug-formatted, or [`REDACTED`] for each one if redaction is on.
+    pub params: Vec<String>,
+    /// How long the execution took.
+    pub duration: Duration,
+    /// Number of rows returned or affected.
+    pub rows: u64,
+}
+
+/// A bounded, in-memory log of the most recent queries.
+///
+/// Once `capacity` records are stored, recording a new query drops the oldest one.
+#[derive(Debug)]
+pub struct QueryRecorder {
+    capacity: usize,
+    redact_params: bool,
+    records: Mutex<VecDeque<QueryRecord>>,
+}
+
+impl QueryRecorder {
+    /// Creates a recorder keeping at most `capacity` queries.
+    ///
+    /// With `redact_params`, the values of bound parameters are never stored.
+    pub fn new(capacity: usize, redact_params: bool) -> Self {
+        QueryRecorder {
+            capacity,
+            redact_params,
+            records: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    /// Records a query execution.
+    pub fn record(&self, sql: &str, params: &[&dyn fmt::Debug], duration: Duration, rows: u64) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let params = params
+            .iter()
+            .map(|p| {
+                if self.redact_params {
+                    REDACTED.to_owned()
+                } else {
+                    format!("{:?}", p)
+                }
+            })
+            .collect();
+
+        let mut records = self.records.lock().unwrap_or_else(|e| e.into_inner());
+        if records.len() == self.capacity {
+            records.pop_front();
+        }
+        records.push_back(QueryRecord {
+            sql: sql.to_owned(),
+            params,
+            duration,
+            rows,
+        });
+    }
+
+    /// Returns the recorded queries, oldest first.
+    pub fn recent(&self) -> Vec<QueryRecord> {
+        let records = self.records.lock().unwrap_or_else(|e| e.into_inner());
+        records.iter().cloned().collect()
+    }
+
+    /// Removes all recorded queries.
+    pub fn clear(&self) {
+        self.records
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+}
+
+/// Returns the global recorder, configuring it on first use.
+///
+/// The capacity is read from `database.query_recorder_capacity` (default 100) and parameter
+/// redaction from `database.redact_params` (default `true`).
+pub fn global_recorder() -> &'static QueryRecorder {
+    RECORDER.get_or_init(|| {
+        QueryRecorder::new(
+            read_config!("database.query_recorder_capacity", usize).unwrap_or(DEFAULT_CAPACITY),
+            read_config!("database.redact_params", bool).unwrap_or(true),
+        )
+    })
+}
+
+/// Records a query execution in the global recorder.
+///
+/// The query helpers of this crate record their queries automatically, with their bind values,
+/// once they succeed; call this to add queries that are executed directly through sqlx.
+pub fn record_query(sql: &str, params: &[&dyn fmt::Debug], duration: Duration, rows: u64) {
+    global_recorder().record(sql, params, duration, rows);
+}
+
+/// A JSON bind value, recorded as JSON (`42`, `"bob"`) rather than as `Number(42)`.
+struct JsonParam<'a>(&'a Value);
+
+impl fmt::Debug for JsonParam<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self.0, f)
+    }
+}
+
+/// Records a query a helper of this crate started at `started`, with JSON bind values.
+pub(crate) fn record_values(sql: &str, binds: &[Value], started: Instant, rows: u64) {
+    let params: Vec<JsonParam<'_>> = binds.iter().map(JsonParam).collect();
+    let params: Vec<&dyn fmt::Debug> = params.iter().map(|p| p as &dyn fmt::Debug).collect();
+    record_query(sql, &params, started.elapsed(), rows);
+}
+
+/// Records a streamed query once its stream is dropped, with the number of rows read so far.
+pub(crate) struct StreamRecording {
+    sql: String,
+    binds: Vec<Value>,
+    started: Instant,
+    rows: u64,
+}
+
+impl StreamRecording {
+    /// Starts timing a streamed query.
+    pub(crate) fn start(sql: &str, binds: &[Value]) -> Self {
+        StreamRecording {
+            sql: sql.to_owned(),
+            binds: binds.to_vec(),
+            started: Instant::now(),
+            rows: 0,
+        }
+    }
+
+    /// Counts a row read from the stream.
+    pub(crate) fn row(&mut self) {
+        self.rows += 1;
+    }
+}
+
+impl Drop for StreamRecording {
+    fn drop(&mut self) {
+        record_values(&self.sql, &self.binds, self.started, self.rows);
+    }
+}
+
+/// Returns the most recent queries recorded in the global recorder, oldest first.
+pub fn recent_queries() -> Vec<QueryRecord> {
+    global_recorder().recent()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recorder_keeps_queries_in_order() {
+        let recorder = QueryRecorder::new(10, false);
+        recorder.record("SELECT 1", &[], Duration::from_millis(1), 1);
+        recorder.record(
+            "SELECT * FROM users WHERE id = ?",
+            &[&42],
+            Duration::from_millis(2),
+            1,
+        );
+        recorder.record(
+            "UPDATE users SET name = ?",
+            &[&"bob"],
+            Duration::from_millis(3),
+            7,
+        );
+
+        let records = recorder.recent();
+        let sql: Vec<_> = records.iter().map(|r| r.sql.as_str()).collect();
+        assert_eq!(
+            sql,
+            [
+                "SELECT 1",
+                "SELECT * FROM users WHERE id = ?",
+                "UPDATE users SET name = ?"
+            ]
+        );
+        let rows: Vec<_> = records.iter().map(|r| r.rows).collect();
+        assert_eq!(rows, [1, 1, 7]);
+        assert_eq!(records[1].params, ["42"]);
+        assert_eq!(records[2].params, ["\"bob\""]);
+    }
+
+    #[test]
+    fn test_recorder_drops_oldest_when_full() {
+        let recorder = QueryRecorder::new(2, false);
+        for i in 0..5_u64 {
+            recorder.record(&format!("SELECT {}", i), &[], Duration::ZERO, i);
+        }
+
+        let sql: Vec<_> = recorder.recent().into_iter().map(|r| r.sql).collect();
+        assert_eq!(sql, ["SELECT 3", "SELECT 4"]);
+    }
+
+    #[test]
+    fn test_helpers_record_sql_binds_and_rows() {
+        crate::test_util::run_with_db(|_| async {
+            use serde_json::json;
+            register_no_redaction();
+
+            let sql = "SELECT ? + ? AS total";
+            let rows: Vec<(i64,)> =
+                crate::pagination::fetch_page(sql, &[json!(2), json!(3)], 10, 0)
+                    .await
+                    .unwrap();
+            assert_eq!(rows, [(5,)]);
+
+            let record = recent_queries()
+                .into_iter()
+                .rev()
+                .find(|r| r.sql.starts_with(sql))
+                .expect("fetch_page was not recorded");
+            assert_eq!(record.sql, "SELECT ? + ? AS total LIMIT ? OFFSET ?");
+            // Another test may have set up the global recorder first, with redaction on.
+            let expected = if global_recorder().redact_params {
+                vec![REDACTED; 4]
+            } else {
+                vec!["2", "3", "10", "0"]
+            };
+            assert_eq!(record.params, expected);
+            assert_eq!(record.rows, 1);
+        });
+    }
+
+    /// Makes the global recorder keep bind values, whatever the configuration says.
+    fn register_no_redaction() {
+        let _ = RECORDER.set(QueryRecorder::new(DEFAULT_CAPACITY, false));
+    }
+
+    #[test]
+    fn test_stream_recording_counts_rows_until_dropped() {
+        register_no_redaction();
+        let mut recording = StreamRecording::start("SELECT * FROM zirv_test_stream_recording", &[]);
+        recording.row();
+        recording.row();
+        drop(recording);
+
+        let record = recent_queries()
+            .into_iter()
+            .rev()
+            .find(|r| r.sql == "SELECT * FROM zirv_test_stream_recording")
+            .unwrap();
+        assert_eq!(record.rows, 2);
+    }
+
+    #[test]
+    fn test_recorder_redacts_params() {
+        let recorder = QueryRecorder::new(10, true);
+        recorder.record(
+            "SELECT * FROM users WHERE email = ?",
+            &[&"secret@example.com"],
+            Duration::ZERO,
+            0,
+        );
+        assert_eq!(recorder.recent()[0].params, [REDACTED]);
+    }
+}
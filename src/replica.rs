@@ -0,0 +1,162 @@
+use std::time::Duration;
+
+use serde_json::{Map, Value};
+use sqlx::{MySql, Pool};
+
+use crate::db::{get_write_pool, replica_pool};
+use crate::logging::log_warn;
+use crate::redact::format_db_error;
+use crate::row::row_to_json;
+
+/// Status columns holding the replication delay, by server version.
+const LAG_COLUMNS: &[&str] = &["Seconds_Behind_Source", "Seconds_Behind_Master"];
+
+/// Returns how far the replica behind `database.read_url` lags behind its source.
+///
+/// Reads `Seconds_Behind_Source` from `SHOW REPLICA STATUS`, falling back to
+/// `SHOW SLAVE STATUS` (`Seconds_Behind_Master`) on servers that predate the new syntax.
+///
+/// # Returns
+/// `Ok(None)` if no read pool is configured, the server is not a replica, or replication is not
+/// running (the server reports no delay then).
+pub async fn replica_lag() -> Result<Option<Duration>, sqlx::Error> {
+    let Some(pool) = replica_pool() else {
+        return Ok(None);
+    };
+    let status = match sqlx::query("SHOW REPLICA STATUS")
+        .fetch_optional(pool)
+        .await
+    {
+        Ok(status) => status,
+        Err(sqlx::Error::Database(_)) => {
+            sqlx::query("SHOW SLAVE STATUS")
+                .fetch_optional(pool)
+                .await?
+        }
+        Err(e) => return Err(e),
+    };
+    Ok(status.and_then(|row| lag_from_status(&row_to_json(&row))))
+}
+
+/// Extracts the replication delay from a replica status row.
+fn lag_from_status(status: &Map<String, Value>) -> Option<Duration> {
+    LAG_COLUMNS
+        .iter()
+        .find_map(|column| status.get(*column))
+        .and_then(|lag| match lag {
+            Value::Number(n) => n.as_u64(),
+            Value::String(s) => s.parse().ok(),
+            _ => None,
+        })
+        .map(Duration::from_secs)
+}
+
+/// Picks the pool for a read tolerating `max_stale`, given the replica's measured lag.
+///
+/// An unknown lag (replication stopped, or the check failed) counts as too stale.
+fn choose_pool<'p>(
+    lag: Option<Duration>,
+    max_stale: Duration,
+    replica: &'p Pool<MySql>,
+    primary: &'p Pool<MySql>,
+) -> &'p Pool<MySql> {
+    if lag.is_some_and(|lag| lag <= max_stale) {
+        replica
+    } else {
+        primary
+    }
+}
+
+/// Runs a read on the replica if its data is at most `max_stale` old, and on the primary
+/// otherwise.
+///
+/// The replica's lag is checked with [`replica_lag`] before every call, which costs one extra
+/// round-trip to the replica; a failed check falls back to the primary with a warning. Without
+/// a `database.read_url`, the query always runs on the primary. `Duration::ZERO` only accepts a
+/// replica that reports no delay at all.
+///
+/// # Example
+/// ```rust,no_run
+/// use std::time::Duration;
+/// use zirv_db_sqlx::replica::read_with_staleness;
+///
+/// async fn dashboard_count() -> Result<i64, sqlx::Error> {
+///     read_with_staleness(Duration::from_secs(5), async |pool| {
+///         sqlx::query_scalar("SELECT COUNT(*) FROM orders")
+///             .fetch_one(pool)
+///             .await
+///     })
+///     .await
+/// }
+/// ```
+pub async fn read_with_staleness<F, T>(max_stale: Duration, query: F) -> Result<T, sqlx::Error>
+where
+    F: AsyncFnOnce(&'static Pool<MySql>) -> Result<T, sqlx::Error>,
+{
+    let pool = match replica_pool() {
+        Some(replica) => {
+            let lag = replica_lag().await.unwrap_or_else(|e| {
+                log_warn(format_args!(
+                    "Failed to check the replica lag, reading from the primary: {}",
+                    format_db_error(&e)
+                ));
+                None
+            });
+            choose_pool(lag, max_stale, replica, get_write_pool())
+        }
+        None => get_write_pool(),
+    };
+    query(pool).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{database_of, lazy_pool};
+    use serde_json::json;
+
+    fn status(value: Value) -> Map<String, Value> {
+        let Value::Object(status) = value else {
+            unreachable!()
+        };
+        status
+    }
+
+    #[test]
+    fn test_lag_from_status() {
+        assert_eq!(
+            lag_from_status(&status(json!({ "Seconds_Behind_Source": 3 }))),
+            Some(Duration::from_secs(3))
+        );
+        assert_eq!(
+            lag_from_status(&status(json!({ "Seconds_Behind_Master": "12" }))),
+            Some(Duration::from_secs(12))
+        );
+        // Replication is not running.
+        assert_eq!(
+            lag_from_status(&status(json!({ "Seconds_Behind_Source": null }))),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_routing_depends_on_lag_and_tolerance() {
+        let (replica, primary) = (lazy_pool("replica"), lazy_pool("primary"));
+        let route = |lag: Option<u64>, max_stale: u64| {
+            database_of(choose_pool(
+                lag.map(Duration::from_secs),
+                Duration::from_secs(max_stale),
+                &replica,
+                &primary,
+            ))
+        };
+
+        // Lag below or at the tolerance reads from the replica.
+        assert_eq!(route(Some(2), 5), "replica");
+        assert_eq!(route(Some(5), 5), "replica");
+        assert_eq!(route(Some(0), 0), "replica");
+        // Lag above the tolerance, or unknown lag, reads from the primary.
+        assert_eq!(route(Some(6), 5), "primary");
+        assert_eq!(route(None, 5), "primary");
+    }
+}
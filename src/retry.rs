@@ -0,0 +1,657 @@
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::BuildHasher;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use serde_json::Value;
+use sqlx::MySqlConnection;
+use sqlx::error::DatabaseError;
+
+use crate::config::{get_bool, get_string, get_u64, resolve_pool_config};
+use crate::db;
+use crate::error::DbError;
+use crate::idle_stack::Acquired;
+use crate::logging::log_warn;
+use crate::redact::format_db_error;
+
+/// Delay before the first retry. Each further retry doubles it, up to [`MAX_DELAY`].
+const BASE_DELAY: Duration = Duration::from_millis(50);
+
+/// Upper bound for the delay between two attempts.
+const MAX_DELAY: Duration = Duration::from_secs(2);
+
+/// MySQL error numbers that indicate a transient condition worth retrying.
+///
+/// - `1040`: too many connections
+/// - `1205`: lock wait timeout exceeded
+/// - `1213`: deadlock found when trying to get lock
+/// - `2006`: server has gone away
+/// - `2013`: lost connection to server during query
+const RETRYABLE_ERROR_NUMBERS: &[u16] = &[1040, 1205, 1213, 2006, 2013];
+
+/// MySQL error number for "Deadlock found when trying to get lock".
+const ER_LOCK_DEADLOCK: u16 = 1213;
+
+/// MySQL error number for "Lock wait timeout exceeded", for row locks and metadata locks alike.
+const ER_LOCK_WAIT_TIMEOUT: u16 = 1205;
+
+/// MySQL error number for "The MySQL server is running with the --... option so it cannot
+/// execute this statement", which covers `read_only` among other options.
+const ER_OPTION_PREVENTS_STATEMENT: u16 = 1290;
+
+/// MySQL error number for "Running in read-only mode".
+const ER_READ_ONLY_MODE: u16 = 1836;
+
+/// Delay before a write that hit a read-only server is retried.
+const READ_ONLY_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Returns the MySQL error number of a database error.
+///
+/// Errors that are not a `MySqlDatabaseError` fall back to parsing their `code()`, which lets
+/// other `DatabaseError` implementations report the error number directly.
+pub(crate) fn error_number(err: &dyn DatabaseError) -> Option<u16> {
+    match err.try_downcast_ref::<sqlx::mysql::MySqlDatabaseError>() {
+        Some(mysql) => Some(mysql.number()),
+        None => err.code().and_then(|code| code.parse().ok()),
+    }
+}
+
+/// Classifies an error as transient, i.e. likely to succeed if the operation is retried.
+///
+/// I/O errors, pool acquire timeouts and a small set of MySQL server errors (lost connections,
+/// too many connections, deadlocks and lock wait timeouts) are considered retryable. Everything
+/// else, such as syntax errors or constraint violations, is not.
+pub fn is_retryable(err: &sqlx::Error) -> bool {
+    match err {
+        sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut => true,
+        sqlx::Error::Database(db) => {
+            db.is_transient_in_connect_phase()
+                || error_number(db.as_ref()).is_some_and(|n| RETRYABLE_ERROR_NUMBERS.contains(&n))
+        }
+        _ => false,
+    }
+}
+
+/// Returns `true` if the error is a deadlock, after which the server has rolled back the whole
+/// transaction and it can only be retried from the start.
+pub fn is_deadlock(err: &sqlx::Error) -> bool {
+    match err {
+        sqlx::Error::Database(db) => error_number(db.as_ref()) == Some(ER_LOCK_DEADLOCK),
+        _ => false,
+    }
+}
+
+/// Returns `true` if the error is a lock wait timeout.
+///
+/// A DML statement reports this when a row lock is held for longer than
+/// `innodb_lock_wait_timeout`; a DDL statement reports it when a metadata lock is held for
+/// longer than `lock_wait_timeout`.
+pub fn is_lock_wait_timeout(err: &sqlx::Error) -> bool {
+    match err {
+        sqlx::Error::Database(db) => error_number(db.as_ref()) == Some(ER_LOCK_WAIT_TIMEOUT),
+        _ => false,
+    }
+}
+
+/// Returns `true` if the error is a write rejected because the server is read-only.
+///
+/// This is error `1836`, or error `1290` caused by the `read_only` or `super_read_only`
+/// options (`1290` is also used for other options, such as `--secure-file-priv`). A replica,
+/// or a primary that is being demoted during a failover, answers writes this way.
+pub fn is_read_only(err: &sqlx::Error) -> bool {
+    let sqlx::Error::Database(db) = err else {
+        return false;
+    };
+    match error_number(db.as_ref()) {
+        Some(ER_READ_ONLY_MODE) => true,
+        Some(ER_OPTION_PREVENTS_STATEMENT) => db.message().contains("read-only"),
+        _ => false,
+    }
+}
+
+static ACQUIRE_BACKOFF: OnceLock<(AcquireBackoff, Mutex<JitterRng>)> = OnceLock::new();
+
+/// How the delay between two acquire attempts grows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum BackoffStrategy {
+    /// Wait `base` between every attempt (`"fixed"`).
+    Fixed,
+    /// Double the delay after every attempt, starting at `base` (`"exponential"`, the default).
+    #[default]
+    Exponential,
+}
+
+/// The backoff between attempts of the helpers that retry acquiring a connection, such as
+/// [`retry`] and `start_transaction_retry!`.
+///
+/// Read from the `database.acquire_backoff` object:
+///
+/// ```json
+/// { "strategy": "exponential", "base_ms": 50, "max_ms": 2000, "jitter": true }
+/// ```
+///
+/// Every key is optional; the defaults are shown above. With `jitter`, each delay is drawn
+/// uniformly from the upper half of the computed delay, so that callers retrying at the same
+/// time spread out instead of hitting the saturated pool together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AcquireBackoff {
+    /// How the delay grows between attempts.
+    pub strategy: BackoffStrategy,
+    /// Delay before the first retry.
+    pub base: Duration,
+    /// Upper bound for any delay.
+    pub max: Duration,
+    /// Whether to randomize the delays.
+    pub jitter: bool,
+}
+
+impl Default for AcquireBackoff {
+    fn default() -> Self {
+        AcquireBackoff {
+            strategy: BackoffStrategy::Exponential,
+            base: BASE_DELAY,
+            max: MAX_DELAY,
+            jitter: true,
+        }
+    }
+}
+
+impl AcquireBackoff {
+    /// Builds the backoff from the value of `database.acquire_backoff`, where `null` means the
+    /// defaults.
+    ///
+    /// # Errors
+    /// Returns [`DbError::Config`] if the value is not an object or a setting is invalid.
+    pub fn from_value(value: &Value) -> Result<Self, DbError> {
+        if value.is_null() {
+            return Ok(AcquireBackoff::default());
+        }
+        if !value.is_object() {
+            return Err(DbError::Config(format!(
+                "database.acquire_backoff must be an object, found {}",
+                value
+            )));
+        }
+
+        let defaults = AcquireBackoff::default();
+        let strategy = match get_string(value, "strategy")
+            .map_err(in_section)?
+            .as_deref()
+        {
+            None => defaults.strategy,
+            Some("fixed") => BackoffStrategy::Fixed,
+            Some("exponential") => BackoffStrategy::Exponential,
+            Some(other) => {
+                return Err(DbError::Config(format!(
+                    "database.acquire_backoff.strategy must be \"fixed\" or \"exponential\", found {:?}",
+                    other
+                )));
+            }
+        };
+        let base = get_u64(value, "base_ms")
+            .map_err(in_section)?
+            .map_or(defaults.base, Duration::from_millis);
+        let max = get_u64(value, "max_ms")
+            .map_err(in_section)?
+            .map_or(defaults.max, Duration::from_millis);
+        let jitter = get_bool(value, "jitter")
+            .map_err(in_section)?
+            .unwrap_or(defaults.jitter);
+
+        Ok(AcquireBackoff {
+            strategy,
+            base,
+            max,
+            jitter,
+        })
+    }
+
+    /// Computes the delay before retry number `attempt` (starting at 1), drawing the jitter
+    /// from `rng`.
+    pub fn delay(&self, attempt: u32, rng: &mut JitterRng) -> Duration {
+        let delay = match self.strategy {
+            BackoffStrategy::Fixed => self.base,
+            BackoffStrategy::Exponential => self
+                .base
+                .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1))),
+        }
+        .min(self.max);
+
+        if !self.jitter {
+            return delay;
+        }
+        let half = delay / 2;
+        let spread = u64::try_from((delay - half).as_nanos()).unwrap_or(u64::MAX);
+        half + Duration::from_nanos(rng.next_u64() % spread.saturating_add(1))
+    }
+}
+
+/// Points an error about a key of `database.acquire_backoff` at the nested key.
+fn in_section(err: DbError) -> DbError {
+    match err {
+        DbError::Config(msg) => {
+            DbError::Config(msg.replacen("database.", "database.acquire_backoff.", 1))
+        }
+        other => other,
+    }
+}
+
+/// A small, seedable random number generator (xorshift64) for backoff jitter.
+///
+/// It is not suitable for anything security related.
+#[derive(Debug, Clone)]
+pub struct JitterRng(u64);
+
+impl JitterRng {
+    /// Creates a generator from `seed`; the same seed always yields the same delays.
+    pub fn new(seed: u64) -> Self {
+        // xorshift never leaves the all-zero state.
+        JitterRng(if seed == 0 {
+            0x9E37_79B9_7F4A_7C15
+        } else {
+            seed
+        })
+    }
+
+    /// Creates a generator with a seed that differs between processes.
+    pub fn from_entropy() -> Self {
+        JitterRng::new(RandomState::new().hash_one(std::process::id()))
+    }
+
+    /// Returns the next pseudo-random number.
+    pub fn next_u64(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        x
+    }
+}
+
+/// Computes the delay before acquire retry number `attempt` with the configured backoff, which
+/// falls back to the defaults if the `database` configuration is invalid.
+fn acquire_backoff_delay(attempt: u32) -> Duration {
+    let (backoff, rng) = ACQUIRE_BACKOFF.get_or_init(|| {
+        let backoff =
+            resolve_pool_config().map_or_else(|_| AcquireBackoff::default(), |c| c.acquire_backoff);
+        (backoff, Mutex::new(JitterRng::from_entropy()))
+    });
+    backoff.delay(attempt, &mut rng.lock().unwrap_or_else(|e| e.into_inner()))
+}
+
+/// Computes the backoff delay before retry number `attempt` (starting at 1).
+pub(crate) fn backoff_delay(attempt: u32) -> Duration {
+    BASE_DELAY
+        .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+        .min(MAX_DELAY)
+}
+
+/// Runs `op`, retrying it with backoff while it fails with a retryable error.
+///
+/// The operation is attempted once and then retried at most `max_retries` times, waiting
+/// according to `database.acquire_backoff` (see [`AcquireBackoff`]) in between. Errors that
+/// are not classified as retryable by [`is_retryable`] are returned immediately.
+///
+/// # Example
+/// ```rust,no_run
+/// use zirv_db_sqlx::{db::get_db_pool, retry::retry};
+///
+/// async fn begin() -> Result<(), sqlx::Error> {
+///     let tx = retry(3, || get_db_pool().begin()).await?;
+///     tx.commit().await
+/// }
+/// ```
+pub async fn retry<T, F, Fut>(max_retries: u32, mut op: F) -> Result<T, sqlx::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, sqlx::Error>>,
+{
+    let mut attempt = 0;
+    loop {
+        match op().await {
+            Ok(value) => return Ok(value),
+            Err(e) if attempt < max_retries && is_retryable(&e) => {
+                attempt += 1;
+                tokio::time::sleep(acquire_backoff_delay(attempt)).await;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Runs a write on a connection to the primary, retrying it on a fresh connection while the
+/// server rejects it as read-only.
+///
+/// During a failover, pooled connections may still point at the old primary, which has become
+/// read-only, while the new primary is not reachable yet. When `f` fails with a read-only error
+/// (see [`is_read_only`]), its connection is closed instead of going back to the pool, and `f`
+/// is retried on a new connection from the write pool after one second, up to `max_retries`
+/// times. This only helps if new connections reach the new primary, e.g. through DNS or a
+/// proxy.
+///
+/// # Idempotency
+/// A read-only server rejects a statement before applying it, so after a read-only error the
+/// statement that failed has had no effect. Statements that `f` ran before it on the same
+/// connection did take effect, unless `f` runs them in a transaction; `f` is run again from the
+/// start, so it must either be a single statement, use a transaction, or be safe to repeat.
+///
+/// # Errors
+/// Returns [`DbError::ReadOnly`] if the server is still read-only after all retries, and any
+/// other error of `f` or of acquiring a connection right away.
+///
+/// # Example
+/// ```rust,no_run
+/// use zirv_db_sqlx::retry::retry_write_on_read_only;
+///
+/// async fn deactivate(user_id: i64) -> Result<u64, zirv_db_sqlx::error::DbError> {
+///     retry_write_on_read_only(5, async |conn| {
+///         let result = sqlx::query("UPDATE users SET active = FALSE WHERE id = ?")
+///             .bind(user_id)
+///             .execute(&mut *conn)
+///             .await?;
+///         Ok(result.rows_affected())
+///     })
+///     .await
+/// }
+/// ```
+pub async fn retry_write_on_read_only<F, T>(max_retries: u32, mut f: F) -> Result<T, DbError>
+where
+    F: AsyncFnMut(&mut MySqlConnection) -> Result<T, sqlx::Error>,
+{
+    retry_on_read_only(
+        max_retries,
+        READ_ONLY_RETRY_DELAY,
+        async || db::acquire().await,
+        |conn: Acquired| drop(conn.detach()),
+        async |conn: &mut Acquired| f(conn).await,
+    )
+    .await
+}
+
+/// Runs `f` on connections from `acquire`, discarding each connection that turns out to be
+/// read-only and retrying after `delay`, up to `max_retries` times.
+async fn retry_on_read_only<C, A, D, F, T>(
+    max_retries: u32,
+    delay: Duration,
+    mut acquire: A,
+    mut discard: D,
+    mut f: F,
+) -> Result<T, DbError>
+where
+    A: AsyncFnMut() -> Result<C, sqlx::Error>,
+    D: FnMut(C),
+    F: AsyncFnMut(&mut C) -> Result<T, sqlx::Error>,
+{
+    let mut attempt = 0;
+    loop {
+        let mut conn = acquire().await?;
+        match f(&mut conn).await {
+            Err(e) if is_read_only(&e) => {
+                discard(conn);
+                if attempt == max_retries {
+                    return Err(e.into());
+                }
+                attempt += 1;
+                log_warn(format_args!(
+                    "Write rejected by a read-only server, retrying ({}/{}): {}",
+                    attempt,
+                    max_retries,
+                    format_db_error(&e)
+                ));
+                tokio::time::sleep(delay).await;
+            }
+            result => return result.map_err(Into::into),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[test]
+    fn test_backoff_delay_doubles_and_caps() {
+        assert_eq!(backoff_delay(1), Duration::from_millis(50));
+        assert_eq!(backoff_delay(2), Duration::from_millis(100));
+        assert_eq!(backoff_delay(3), Duration::from_millis(200));
+        assert_eq!(backoff_delay(20), MAX_DELAY);
+    }
+
+    #[test]
+    fn test_acquire_backoff_defaults_to_exponential_with_jitter() {
+        let backoff = AcquireBackoff::from_value(&Value::Null).unwrap();
+        assert_eq!(backoff, AcquireBackoff::default());
+        assert_eq!(backoff.strategy, BackoffStrategy::Exponential);
+        assert!(backoff.jitter);
+    }
+
+    #[test]
+    fn test_acquire_backoff_follows_the_strategy_without_jitter() {
+        let exponential = AcquireBackoff::from_value(&json!({
+            "base_ms": 10, "max_ms": 50, "jitter": false
+        }))
+        .unwrap();
+        let fixed = AcquireBackoff::from_value(&json!({
+            "strategy": "fixed", "base_ms": 25, "jitter": false
+        }))
+        .unwrap();
+        let mut rng = JitterRng::new(1);
+
+        let delays: Vec<u64> = (1..=5)
+            .map(|attempt| exponential.delay(attempt, &mut rng).as_millis() as u64)
+            .collect();
+        assert_eq!(delays, [10, 20, 40, 50, 50]);
+        assert!((1..=5).all(|attempt| fixed.delay(attempt, &mut rng) == Duration::from_millis(25)));
+    }
+
+    #[test]
+    fn test_acquire_backoff_jitter_is_seedable_and_bounded() {
+        let backoff =
+            AcquireBackoff::from_value(&json!({ "base_ms": 100, "max_ms": 800 })).unwrap();
+        let delays = |seed| {
+            let mut rng = JitterRng::new(seed);
+            (1..=6)
+                .map(|attempt| backoff.delay(attempt, &mut rng))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(delays(42), delays(42));
+        assert_ne!(delays(42), delays(43));
+        for (attempt, delay) in (1..=6).zip(delays(42)) {
+            let full = Duration::from_millis(100 * 2u64.pow(attempt - 1)).min(backoff.max);
+            assert!(
+                delay >= full / 2 && delay <= full,
+                "{:?} not in [{:?}, {:?}]",
+                delay,
+                full / 2,
+                full
+            );
+        }
+    }
+
+    #[test]
+    fn test_acquire_backoff_rejects_invalid_settings() {
+        for value in [
+            json!("exponential"),
+            json!({ "strategy": "linear" }),
+            json!({ "base_ms": -1 }),
+            json!({ "jitter": "yes" }),
+        ] {
+            assert!(matches!(
+                AcquireBackoff::from_value(&value),
+                Err(DbError::Config(_))
+            ));
+        }
+        let Err(DbError::Config(msg)) = AcquireBackoff::from_value(&json!({ "max_ms": "2s" }))
+        else {
+            panic!("expected a configuration error");
+        };
+        assert!(
+            msg.starts_with("database.acquire_backoff.max_ms"),
+            "{}",
+            msg
+        );
+    }
+
+    #[test]
+    fn test_is_retryable() {
+        assert!(is_retryable(&sqlx::Error::PoolTimedOut));
+        assert!(is_retryable(&sqlx::Error::Io(std::io::Error::from(
+            std::io::ErrorKind::ConnectionReset
+        ))));
+        assert!(!is_retryable(&sqlx::Error::RowNotFound));
+        assert!(!is_retryable(&sqlx::Error::PoolClosed));
+        assert!(is_retryable(&test_util::db_error(1213, "Deadlock found")));
+        assert!(!is_retryable(&test_util::db_error(1062, "Duplicate entry")));
+    }
+
+    #[test]
+    fn test_is_deadlock() {
+        assert!(is_deadlock(&test_util::db_error(1213, "Deadlock found")));
+        assert!(!is_deadlock(&test_util::db_error(
+            1205,
+            "Lock wait timeout"
+        )));
+        assert!(!is_deadlock(&sqlx::Error::PoolTimedOut));
+    }
+
+    #[test]
+    fn test_is_lock_wait_timeout() {
+        assert!(is_lock_wait_timeout(&test_util::db_error(
+            1205,
+            "Lock wait timeout"
+        )));
+        assert!(!is_lock_wait_timeout(&test_util::db_error(
+            1213,
+            "Deadlock found"
+        )));
+        assert!(!is_lock_wait_timeout(&sqlx::Error::PoolTimedOut));
+    }
+
+    #[test]
+    fn test_read_only_errors_are_classified() {
+        let read_only = test_util::db_error(
+            1290,
+            "The MySQL server is running with the --read-only option so it cannot execute this statement",
+        );
+        assert!(is_read_only(&read_only));
+        assert!(matches!(DbError::from(read_only), DbError::ReadOnly { .. }));
+        assert!(matches!(
+            DbError::from(test_util::db_error(1836, "Running in read-only mode")),
+            DbError::ReadOnly { .. }
+        ));
+
+        let secure_file_priv = test_util::db_error(
+            1290,
+            "The MySQL server is running with the --secure-file-priv option so it cannot execute this statement",
+        );
+        assert!(!is_read_only(&secure_file_priv));
+        assert!(matches!(DbError::from(secure_file_priv), DbError::Sqlx(_)));
+        assert!(!is_read_only(&sqlx::Error::PoolTimedOut));
+    }
+
+    #[tokio::test]
+    async fn test_read_only_writes_are_retried_on_fresh_connections() {
+        let acquired = AtomicU32::new(0);
+        let mut discarded = Vec::new();
+
+        // Connections 0 and 1 still reach the demoted primary, connection 2 the new one.
+        let result = retry_on_read_only(
+            3,
+            Duration::ZERO,
+            async || Ok(acquired.fetch_add(1, Ordering::SeqCst)),
+            |conn| discarded.push(conn),
+            async |conn: &mut u32| match *conn {
+                0 | 1 => Err(test_util::db_error(1836, "Running in read-only mode")),
+                conn => Ok(conn),
+            },
+        )
+        .await;
+        assert_eq!(result.unwrap(), 2);
+        assert_eq!(discarded, [0, 1]);
+
+        let attempts = AtomicU32::new(0);
+        let result: Result<(), _> = retry_on_read_only(
+            2,
+            Duration::ZERO,
+            async || Ok(()),
+            drop,
+            async |_: &mut ()| {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err(test_util::db_error(1836, "Running in read-only mode"))
+            },
+        )
+        .await;
+        assert!(matches!(result, Err(DbError::ReadOnly { .. })));
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_other_write_errors_are_not_retried() {
+        let attempts = AtomicU32::new(0);
+        let result: Result<(), _> = retry_on_read_only(
+            3,
+            Duration::ZERO,
+            async || Ok(()),
+            |_| panic!("connection discarded"),
+            async |_: &mut ()| {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err(test_util::db_error(1062, "Duplicate entry"))
+            },
+        )
+        .await;
+        assert!(matches!(result, Err(DbError::Sqlx(_))));
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+
+    /// The first `begin` fails with a transient acquire error, the second one succeeds.
+    #[tokio::test]
+    async fn test_retry_recovers_from_transient_begin_failure() {
+        let attempts = AtomicU32::new(0);
+
+        let result = retry(3, || async {
+            if attempts.fetch_add(1, Ordering::SeqCst) == 0 {
+                Err(sqlx::Error::PoolTimedOut)
+            } else {
+                Ok("transaction started")
+            }
+        })
+        .await;
+
+        assert_eq!(result.unwrap(), "transaction started");
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_retry_gives_up_after_max_retries() {
+        let attempts = AtomicU32::new(0);
+
+        let result: Result<(), _> = retry(2, || async {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err(sqlx::Error::PoolTimedOut)
+        })
+        .await;
+
+        assert!(matches!(result, Err(sqlx::Error::PoolTimedOut)));
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_retry_does_not_retry_permanent_errors() {
+        let attempts = AtomicU32::new(0);
+
+        let result: Result<(), _> = retry(5, || async {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err(sqlx::Error::RowNotFound)
+        })
+        .await;
+
+        assert!(matches!(result, Err(sqlx::Error::RowNotFound)));
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,400 @@
+use serde_json::{Map, Number, Value};
+use sqlx::mysql::MySqlRow;
+use sqlx::{Column, Row, TypeInfo, ValueRef};
+#[cfg(feature = "serde")]
+use {
+    crate::db::get_db_pool,
+    crate::logging::log_warn,
+    serde::{Serialize, de::DeserializeOwned},
+    sqlx::mysql::MySqlArguments,
+};
+
+/// Converts a row into a JSON object keyed by column name, without knowing its Rust type.
+///
+/// Values are mapped by their MySQL column type: integers and floats become JSON numbers,
+/// `BOOLEAN` becomes a bool, `JSON` columns are embedded as parsed JSON, temporal types become
+/// ISO-8601-like strings, binary types become `0x`-prefixed hex strings and everything else
+/// (text, `DECIMAL`, `ENUM`, ...) becomes a string. `NULL` maps to `null`.
+///
+/// This is meant for debugging and generic tooling; values that cannot be decoded are rendered
+/// as lossy UTF-8 text rather than failing the whole row.
+pub fn row_to_json(row: &MySqlRow) -> Map<String, Value> {
+    row.columns()
+        .iter()
+        .map(|column| {
+            let value = column_to_json(row, column.ordinal());
+            (column.name().to_owned(), value)
+        })
+        .collect()
+}
+
+/// Converts the value at `index` of `row` into JSON. See [`row_to_json`].
+pub fn column_to_json(row: &MySqlRow, index: usize) -> Value {
+    match row.try_get_raw(index) {
+        Ok(raw) if raw.is_null() => return Value::Null,
+        Ok(_) => {}
+        Err(_) => return Value::Null,
+    }
+
+    let type_name = row.column(index).type_info().name();
+    let decoded = match type_name {
+        "BOOLEAN" => row.try_get_unchecked::<bool, _>(index).map(Value::Bool),
+        "FLOAT" | "DOUBLE" => row
+            .try_get_unchecked::<f64, _>(index)
+            .map(|f| Number::from_f64(f).map_or(Value::Null, Value::Number)),
+        name if name.ends_with("UNSIGNED") => row
+            .try_get_unchecked::<u64, _>(index)
+            .map(|n| Value::Number(n.into())),
+        "TINYINT" | "SMALLINT" | "INT" | "MEDIUMINT" | "BIGINT" | "YEAR" => row
+            .try_get_unchecked::<i64, _>(index)
+            .map(|n| Value::Number(n.into())),
+        "JSON" => row
+            .try_get_unchecked::<String, _>(index)
+            .map(|s| serde_json::from_str(&s).unwrap_or(Value::String(s))),
+        "DATE" | "DATETIME" | "TIMESTAMP" => row
+            .try_get_unchecked::<&[u8], _>(index)
+            .map(|bytes| Value::String(format_datetime(bytes))),
+        "TIME" => row
+            .try_get_unchecked::<&[u8], _>(index)
+            .map(|bytes| Value::String(format_time(bytes))),
+        "BINARY" | "VARBINARY" | "TINYBLOB" | "BLOB" | "MEDIUMBLOB" | "LONGBLOB" | "BIT"
+        | "GEOMETRY" => row
+            .try_get_unchecked::<&[u8], _>(index)
+            .map(|bytes| Value::String(format_hex(bytes))),
+        _ => row.try_get_unchecked::<String, _>(index).map(Value::String),
+    };
+
+    decoded.unwrap_or_else(|_| {
+        row.try_get_unchecked::<&[u8], _>(index)
+            .map(|bytes| Value::String(String::from_utf8_lossy(bytes).into_owned()))
+            .unwrap_or(Value::Null)
+    })
+}
+
+/// Returns `true` if `bytes` look like a binary-protocol temporal value whose length prefix is
+/// one of `allowed`, as opposed to a text-protocol value.
+fn is_binary_temporal(bytes: &[u8], allowed: &[u8]) -> bool {
+    bytes
+        .first()
+        .is_some_and(|&len| allowed.contains(&len) && bytes.len() == usize::from(len) + 1)
+}
+
+/// Formats a `DATE`, `DATETIME` or `TIMESTAMP` value.
+fn format_datetime(bytes: &[u8]) -> String {
+    if !is_binary_temporal(bytes, &[0, 4, 7, 11]) {
+        return String::from_utf8_lossy(bytes).into_owned();
+    }
+
+    let b = &bytes[1..];
+    let mut out = match b.len() {
+        0 => return "0000-00-00".to_owned(),
+        _ => format!(
+            "{:04}-{:02}-{:02}",
+            u16::from_le_bytes([b[0], b[1]]),
+            b[2],
+            b[3]
+        ),
+    };
+    if b.len() >= 7 {
+        out.push_str(&format!(" {:02}:{:02}:{:02}", b[4], b[5], b[6]));
+    }
+    if b.len() == 11 {
+        let micros = u32::from_le_bytes([b[7], b[8], b[9], b[10]]);
+        out.push_str(&format!(".{:06}", micros));
+    }
+    out
+}
+
+/// Formats a `TIME` value.
+fn format_time(bytes: &[u8]) -> String {
+    if !is_binary_temporal(bytes, &[0, 8, 12]) {
+        return String::from_utf8_lossy(bytes).into_owned();
+    }
+
+    let b = &bytes[1..];
+    if b.is_empty() {
+        return "00:00:00".to_owned();
+    }
+    let sign = if b[0] == 1 { "-" } else { "" };
+    let days = u32::from_le_bytes([b[1], b[2], b[3], b[4]]);
+    let hours = days * 24 + u32::from(b[5]);
+    let mut out = format!("{}{:02}:{:02}:{:02}", sign, hours, b[6], b[7]);
+    if b.len() == 12 {
+        let micros = u32::from_le_bytes([b[8], b[9], b[10], b[11]]);
+        out.push_str(&format!(".{:06}", micros));
+    }
+    out
+}
+
+/// Formats binary data as a `0x`-prefixed lowercase hex string.
+fn format_hex(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(2 + bytes.len() * 2);
+    out.push_str("0x");
+    for byte in bytes {
+        out.push_str(&format!("{:02x}", byte));
+    }
+    out
+}
+
+/// Runs a query and deserializes every row into `T`, replacing unexpected `NULL`s with the
+/// corresponding field of `T::default()`.
+///
+/// Each row is converted with [`row_to_json`] and deserialized with serde, so `T` must accept
+/// the JSON representation of its columns; its `FromRow` implementation, if any, is not used.
+/// `DATETIME` and `TIMESTAMP` values are passed as RFC 3339 strings instead, as expected by
+/// chrono's serde implementations: `DATETIME` without an offset (for `NaiveDateTime`) and
+/// `TIMESTAMP` in UTC (for `DateTime<Utc>`). Whenever a column is `NULL` but the same field of
+/// `T::default()` is not, the default value is used instead and a warning naming the columns is
+/// logged. Fields that are `Option`s default to `None`, so their `NULL`s are kept.
+///
+/// This hides data problems rather than fixing them; prefer making the fields optional and use
+/// this only for legacy columns. See `query_as_or_default!` for the macro form.
+///
+/// # Errors
+/// Returns the query error, or `sqlx::Error::Decode` if a row still does not deserialize.
+#[cfg(feature = "serde")]
+pub async fn fetch_all_or_default<T>(sql: &str, args: MySqlArguments) -> Result<Vec<T>, sqlx::Error>
+where
+    T: DeserializeOwned + Serialize + Default,
+{
+    crate::statement_cache::track(sql);
+    #[cfg(feature = "query-recorder")]
+    let started = std::time::Instant::now();
+    let rows = sqlx::query_with(sql, args).fetch_all(get_db_pool()).await?;
+    // The arguments are already encoded, so only the SQL is recorded.
+    #[cfg(feature = "query-recorder")]
+    crate::recorder::record_values(sql, &[], started, rows.len() as u64);
+    decode_or_default(sql, rows.iter().map(row_to_serde_json))
+}
+
+/// Converts a row with [`row_to_json`], rewriting its temporal values for serde.
+#[cfg(feature = "serde")]
+fn row_to_serde_json(row: &MySqlRow) -> Map<String, Value> {
+    let mut json = row_to_json(row);
+    for column in row.columns() {
+        if let Some(value) = json.get_mut(column.name()) {
+            temporal_to_rfc3339(column.type_info().name(), value);
+        }
+    }
+    json
+}
+
+/// Rewrites a formatted `DATETIME` value as an RFC 3339 date-time without offset, and a
+/// `TIMESTAMP` value as one in UTC, which is how sqlx decodes them.
+#[cfg(feature = "serde")]
+fn temporal_to_rfc3339(type_name: &str, value: &mut Value) {
+    let Value::String(s) = value else {
+        return;
+    };
+    if !matches!(type_name, "DATETIME" | "TIMESTAMP") || s.as_bytes().get(10) != Some(&b' ') {
+        return;
+    }
+    s.replace_range(10..11, "T");
+    if type_name == "TIMESTAMP" {
+        s.push('Z');
+    }
+}
+
+#[cfg(feature = "serde")]
+fn decode_or_default<T>(
+    sql: &str,
+    rows: impl Iterator<Item = Map<String, Value>>,
+) -> Result<Vec<T>, sqlx::Error>
+where
+    T: DeserializeOwned + Serialize + Default,
+{
+    let defaults = match serde_json::to_value(T::default()) {
+        Ok(Value::Object(defaults)) => defaults,
+        Ok(_) => Map::new(),
+        Err(e) => return Err(sqlx::Error::Decode(Box::new(e))),
+    };
+
+    rows.map(|mut row| {
+        let filled = fill_nulls(&mut row, &defaults);
+        if !filled.is_empty() {
+            log_warn(format_args!(
+                "Replaced NULL with the default value in column(s) {} of a row returned by {:?}",
+                filled.join(", "),
+                sql
+            ));
+        }
+        serde_json::from_value(Value::Object(row)).map_err(|e| sqlx::Error::Decode(Box::new(e)))
+    })
+    .collect()
+}
+
+/// Replaces the `NULL` values of `row` that have a non-null default, returning their columns.
+#[cfg(feature = "serde")]
+fn fill_nulls(row: &mut Map<String, Value>, defaults: &Map<String, Value>) -> Vec<String> {
+    let mut filled = Vec::new();
+    for (column, value) in row.iter_mut() {
+        if let (Value::Null, Some(default)) = (&*value, defaults.get(column))
+            && !default.is_null()
+        {
+            *value = default.clone();
+            filled.push(column.clone());
+        }
+    }
+    filled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_datetime_binary_and_text() {
+        // 2024-03-15 13:45:30.000123 in the binary protocol.
+        let mut bytes = vec![11, 0xE8, 0x07, 3, 15, 13, 45, 30];
+        bytes.extend_from_slice(&123_u32.to_le_bytes());
+        assert_eq!(format_datetime(&bytes), "2024-03-15 13:45:30.000123");
+
+        assert_eq!(format_datetime(&[4, 0xE8, 0x07, 3, 15]), "2024-03-15");
+        assert_eq!(format_datetime(&[0]), "0000-00-00");
+        assert_eq!(
+            format_datetime(b"2024-03-15 13:45:30"),
+            "2024-03-15 13:45:30"
+        );
+    }
+
+    #[test]
+    fn test_format_time_binary() {
+        // -1 day 02:03:04 in the binary protocol.
+        let bytes = [8, 1, 1, 0, 0, 0, 2, 3, 4];
+        assert_eq!(format_time(&bytes), "-26:03:04");
+        assert_eq!(format_time(b"12:00:00"), "12:00:00");
+    }
+
+    #[test]
+    fn test_format_hex() {
+        assert_eq!(format_hex(&[0x00, 0xab, 0x10]), "0x00ab10");
+        assert_eq!(format_hex(&[]), "0x");
+    }
+
+    #[cfg(feature = "serde")]
+    #[derive(Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct LegacyUser {
+        id: i64,
+        name: String,
+        nickname: Option<String>,
+        score: i32,
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_nulls_are_replaced_by_defaults_with_a_warning() {
+        let row = serde_json::json!({ "id": 7, "name": null, "nickname": null, "score": 3 });
+        let Value::Object(row) = row else {
+            unreachable!()
+        };
+
+        let mut users = Vec::new();
+        let logs = crate::test_util::capture_logs(|| {
+            users =
+                decode_or_default::<LegacyUser>("SELECT * FROM users", [row].into_iter()).unwrap();
+        });
+
+        assert_eq!(
+            users,
+            [LegacyUser {
+                id: 7,
+                name: String::new(),
+                nickname: None,
+                score: 3,
+            }]
+        );
+        assert_eq!(
+            logs,
+            "WARN Replaced NULL with the default value in column(s) name of a row returned by \"SELECT * FROM users\"\n"
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_complete_rows_are_decoded_silently() {
+        let row = serde_json::json!({ "id": 1, "name": "Ann", "nickname": "a", "score": 9 });
+        let Value::Object(row) = row else {
+            unreachable!()
+        };
+
+        let mut users = Vec::new();
+        let logs = crate::test_util::capture_logs(|| {
+            users = decode_or_default::<LegacyUser>("SELECT 1", [row].into_iter()).unwrap();
+        });
+
+        assert_eq!(users[0].nickname.as_deref(), Some("a"));
+        assert_eq!(logs, "");
+    }
+
+    #[cfg(feature = "serde")]
+    #[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+    struct LegacyEvent {
+        name: String,
+        created_at: chrono::NaiveDateTime,
+        updated_at: chrono::DateTime<chrono::Utc>,
+        day: chrono::NaiveDate,
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_temporal_values_deserialize_into_chrono_fields() {
+        let mut row = Map::new();
+        for (column, type_name, value) in [
+            ("name", "VARCHAR", Value::Null),
+            (
+                "created_at",
+                "DATETIME",
+                "2024-03-15 13:45:30.000123".into(),
+            ),
+            ("updated_at", "TIMESTAMP", "2024-03-15 13:45:30".into()),
+            ("day", "DATE", "2024-03-15".into()),
+        ] {
+            let mut value = value;
+            temporal_to_rfc3339(type_name, &mut value);
+            row.insert(column.to_owned(), value);
+        }
+        assert_eq!(row["created_at"], "2024-03-15T13:45:30.000123");
+        assert_eq!(row["updated_at"], "2024-03-15T13:45:30Z");
+
+        let mut events = Vec::new();
+        crate::test_util::capture_logs(|| {
+            events = decode_or_default::<LegacyEvent>("SELECT 1", [row].into_iter()).unwrap();
+        });
+
+        assert_eq!(events[0].name, "");
+        assert_eq!(
+            events[0].created_at.to_string(),
+            "2024-03-15 13:45:30.000123"
+        );
+        assert_eq!(
+            events[0].updated_at.to_rfc3339(),
+            "2024-03-15T13:45:30+00:00"
+        );
+        assert_eq!(events[0].day.to_string(), "2024-03-15");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_query_as_or_default_against_database() {
+        crate::test_util::run_with_db(|_| async {
+            let users: Vec<LegacyUser> = crate::query_as_or_default!(
+                LegacyUser,
+                "SELECT CAST(? AS SIGNED) AS id, NULL AS name, NULL AS nickname, CAST(5 AS SIGNED) AS score",
+                7
+            )
+            .unwrap();
+            assert_eq!(users[0].id, 7);
+            assert_eq!(users[0].name, "");
+            assert_eq!(users[0].score, 5);
+
+            let events: Vec<LegacyEvent> = crate::query_as_or_default!(
+                LegacyEvent,
+                "SELECT NULL AS name, CAST('2024-03-15 13:45:30' AS DATETIME) AS created_at, \
+                 TIMESTAMP('2024-03-15 13:45:30') AS updated_at, DATE('2024-03-15') AS day"
+            )
+            .unwrap();
+            assert_eq!(events[0].created_at.to_string(), "2024-03-15 13:45:30");
+            assert_eq!(events[0].day.to_string(), "2024-03-15");
+        });
+    }
+}
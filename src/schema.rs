@@ -0,0 +1,775 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use serde_json::Value;
+use sqlx::mysql::{MySqlConnectOptions, MySqlPoolOptions, MySqlRow};
+use sqlx::{FromRow, MySql, Pool};
+use zirv_config::read_config;
+
+use crate::config::resolve_pool_config;
+use crate::db::{acquire, connect_options, get_db_pool, pool_options};
+use crate::error::DbError;
+use crate::logging::log_warn;
+use crate::redact::format_db_error;
+use crate::retry::is_lock_wait_timeout;
+use crate::sql::{json_arguments, quote_identifier, quote_unqualified_identifier};
+use crate::transaction::{LongTransaction, long_running_transactions};
+
+/// Default maximum number of schema pools kept open at once.
+const DEFAULT_MAX_SCHEMA_POOLS: usize = 16;
+
+static SCHEMA_POOLS: OnceLock<SchemaPools> = OnceLock::new();
+
+#[derive(Debug, Default)]
+struct CacheState {
+    /// Schema name mapped to its pool and the tick it was last requested at.
+    pools: HashMap<String, (Pool<MySql>, u64)>,
+    tick: u64,
+}
+
+/// Pools for several schemas (databases) on the same server, created on first use.
+///
+/// Every pool is built from the same connect and pool options, only the database name differs.
+/// At most `max_pools` pools are kept (`0` means no limit): requesting a new schema when the
+/// cache is full evicts the least recently requested one. An evicted pool is not closed while clones of it are still in
+/// use; its connections are released once the last clone is dropped.
+#[derive(Debug)]
+pub struct SchemaPools {
+    connect_options: MySqlConnectOptions,
+    pool_options: MySqlPoolOptions,
+    max_pools: usize,
+    state: Mutex<CacheState>,
+}
+
+impl SchemaPools {
+    /// Creates an empty cache building pools from the given options.
+    pub fn new(
+        connect_options: MySqlConnectOptions,
+        pool_options: MySqlPoolOptions,
+        max_pools: usize,
+    ) -> Self {
+        SchemaPools {
+            connect_options,
+            pool_options,
+            max_pools,
+            state: Mutex::new(CacheState::default()),
+        }
+    }
+
+    /// Returns the pool for `schema`, creating it if needed.
+    ///
+    /// The pool connects lazily, so this never waits for the database.
+    ///
+    /// # Errors
+    /// Returns [`DbError::InvalidIdentifier`] if `schema` is not a valid schema name.
+    pub fn get(&self, schema: &str) -> Result<Pool<MySql>, DbError> {
+        quote_unqualified_identifier(schema)?;
+
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.tick += 1;
+        let tick = state.tick;
+
+        if let Some((pool, last_used)) = state.pools.get_mut(schema) {
+            *last_used = tick;
+            return Ok(pool.clone());
+        }
+
+        if self.max_pools > 0 && state.pools.len() >= self.max_pools {
+            let oldest = state
+                .pools
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(schema, _)| schema.clone());
+            if let Some(oldest) = oldest {
+                state.pools.remove(&oldest);
+            }
+        }
+
+        let pool = self
+            .pool_options
+            .clone()
+            .connect_lazy_with(self.connect_options.clone().database(schema));
+        state.pools.insert(schema.to_owned(), (pool.clone(), tick));
+        Ok(pool)
+    }
+
+    /// Returns the number of pools currently cached.
+    pub fn len(&self) -> usize {
+        self.state
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .pools
+            .len()
+    }
+
+    /// Returns `true` if no pool has been created yet (or all were evicted).
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Returns a pool connected to `schema` on the configured server.
+///
+/// The pool uses the same settings as the global pool (URL, credentials, pool sizes, timeouts)
+/// except for the database name. Pools are created on first use and cached; at most
+/// `database.max_schema_pools` (default 16) are kept, evicting the least recently used.
+///
+/// # Errors
+/// Returns [`DbError::Config`] if the `database` configuration is invalid and
+/// [`DbError::InvalidIdentifier`] if `schema` is not a valid schema name.
+///
+/// # Example
+/// ```rust,no_run
+/// use zirv_db_sqlx::schema::get_schema_pool;
+///
+/// # async fn example() -> Result<(), zirv_db_sqlx::error::DbError> {
+/// let pool = get_schema_pool("customer_42")?;
+/// let invoices: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM invoices")
+///     .fetch_one(&pool)
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn get_schema_pool(schema: &str) -> Result<Pool<MySql>, DbError> {
+    if let Some(pools) = SCHEMA_POOLS.get() {
+        return pools.get(schema);
+    }
+
+    let config = resolve_pool_config()?;
+    let options = connect_options(&config, &config.url)?;
+    let max_pools =
+        read_config!("database.max_schema_pools", usize).unwrap_or(DEFAULT_MAX_SCHEMA_POOLS);
+    SCHEMA_POOLS
+        .get_or_init(|| SchemaPools::new(options, pool_options(&config), max_pools))
+        .get(schema)
+}
+
+/// Placeholder that [`qualify_schema`] replaces with the quoted schema name where it is
+/// followed by `.`.
+pub const SCHEMA_PLACEHOLDER: &str = "{schema}";
+
+/// Replaces every `{schema}.` in `sql` with the quoted name of `schema` followed by `.`.
+///
+/// Write the tables of the other schema as `{schema}.table`; tables without the placeholder
+/// still refer to the connection's default database. A `{schema}` that is not followed by `.`
+/// is left as is. The SQL is not parsed, so a `{schema}.` inside a string literal or comment is
+/// replaced too; bind such values as parameters instead.
+///
+/// # Errors
+/// Returns [`DbError::InvalidIdentifier`] if `schema` is not a valid schema name.
+///
+/// # Example
+/// ```rust
+/// use zirv_db_sqlx::schema::qualify_schema;
+///
+/// assert_eq!(
+///     qualify_schema("SELECT * FROM {schema}.invoices", "customer_42").unwrap(),
+///     "SELECT * FROM `customer_42`.invoices"
+/// );
+/// ```
+pub fn qualify_schema(sql: &str, schema: &str) -> Result<String, DbError> {
+    let schema = quote_unqualified_identifier(schema)?;
+    let placeholder = format!("{}.", SCHEMA_PLACEHOLDER);
+    Ok(sql.replace(&placeholder, &format!("{}.", schema)))
+}
+
+/// Runs a query against the tables of `schema`, using the global pool.
+///
+/// The tables to read from `schema` are written as `{schema}.table` in `sql` (see
+/// [`qualify_schema`]), and binds are bound as described in [`json_arguments`]. Qualifying the
+/// names, rather than switching the connection's database with `USE`, leaves no session state
+/// behind on the pooled connection and needs no pool per schema (unlike [`get_schema_pool`]).
+///
+/// # Errors
+/// Returns [`DbError::InvalidIdentifier`] if `schema` is not a valid schema name, or
+/// [`DbError::Sqlx`] if the query fails.
+///
+/// # Example
+/// ```rust,no_run
+/// use serde_json::json;
+/// use zirv_db_sqlx::schema::on_schema;
+///
+/// async fn open_invoices(customer: &str) -> Result<Vec<(i64, i64)>, zirv_db_sqlx::error::DbError> {
+///     on_schema(
+///         customer,
+///         "SELECT id, total FROM {schema}.invoices WHERE status = ?",
+///         &[json!("open")],
+///     )
+///     .await
+/// }
+/// ```
+pub async fn on_schema<T>(schema: &str, sql: &str, binds: &[Value]) -> Result<Vec<T>, DbError>
+where
+    T: for<'r> FromRow<'r, MySqlRow> + Send + Unpin,
+{
+    let sql = qualify_schema(sql, schema)?;
+    let args = json_arguments(binds)?;
+    crate::statement_cache::track(&sql);
+    #[cfg(feature = "query-recorder")]
+    let started = std::time::Instant::now();
+    let rows: Vec<T> = sqlx::query_as_with(&sql, args)
+        .fetch_all(get_db_pool())
+        .await?;
+    #[cfg(feature = "query-recorder")]
+    crate::recorder::record_values(&sql, binds, started, rows.len() as u64);
+    Ok(rows)
+}
+
+/// A table or column whose collation differs from the expected one.
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct CollationMismatch {
+    /// The table name.
+    pub table: String,
+    /// The column name, or `None` if the mismatch is the table's default collation.
+    pub column: Option<String>,
+    /// The actual collation.
+    pub collation: String,
+}
+
+/// Reports every table and text column of the current database whose collation is not
+/// `expected`.
+///
+/// Table default collations come from `information_schema.tables`, column collations from
+/// `information_schema.columns`; columns without a collation (numbers, dates, binary strings)
+/// are ignored. Results are ordered by table, with the table's own entry before its columns.
+///
+/// # Example
+/// ```rust,no_run
+/// use zirv_db_sqlx::schema::check_table_collations;
+///
+/// # async fn example() -> Result<(), sqlx::Error> {
+/// for mismatch in check_table_collations("utf8mb4_0900_ai_ci").await? {
+///     eprintln!("{:?}", mismatch);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub async fn check_table_collations(expected: &str) -> Result<Vec<CollationMismatch>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT CAST(TABLE_NAME AS CHAR) AS `table`, CAST(NULL AS CHAR) AS `column`, \
+                CAST(TABLE_COLLATION AS CHAR) AS collation \
+         FROM information_schema.tables \
+         WHERE TABLE_SCHEMA = DATABASE() AND TABLE_TYPE = 'BASE TABLE' AND TABLE_COLLATION <> ? \
+         UNION ALL \
+         SELECT CAST(TABLE_NAME AS CHAR), CAST(COLUMN_NAME AS CHAR), CAST(COLLATION_NAME AS CHAR) \
+         FROM information_schema.columns \
+         WHERE TABLE_SCHEMA = DATABASE() AND COLLATION_NAME IS NOT NULL AND COLLATION_NAME <> ? \
+         ORDER BY 1, 2",
+    )
+    .bind(expected)
+    .bind(expected)
+    .fetch_all(get_db_pool())
+    .await
+}
+
+/// A column of a table, as described by `information_schema.columns`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ColumnInfo {
+    /// The column name.
+    pub name: String,
+    /// The base type without length or modifiers, e.g. `varchar`.
+    pub data_type: String,
+    /// The full type, e.g. `varchar(255)` or `int unsigned`.
+    pub column_type: String,
+    /// Whether the column accepts `NULL`.
+    pub nullable: bool,
+    /// The default value as written in the schema, or `None` if there is none.
+    pub default: Option<String>,
+    /// The index the column belongs to first: `PRI`, `UNI`, `MUL`, or an empty string.
+    pub key: String,
+}
+
+/// Lists the columns of `table`, in the order they are defined.
+///
+/// `table` may be qualified with its schema (`schema.table`); otherwise it is looked up in the
+/// current database. The name is validated with [`quote_identifier`] and bound as a parameter.
+///
+/// # Returns
+/// An empty list if the table does not exist.
+///
+/// # Errors
+/// Returns [`DbError::InvalidIdentifier`] if `table` is not a valid name, or [`DbError::Sqlx`]
+/// if the query fails.
+///
+/// # Example
+/// ```rust,no_run
+/// use zirv_db_sqlx::schema::describe_table;
+///
+/// # async fn example() -> Result<(), zirv_db_sqlx::error::DbError> {
+/// for column in describe_table("users").await? {
+///     println!("{} {} nullable={}", column.name, column.column_type, column.nullable);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub async fn describe_table(table: &str) -> Result<Vec<ColumnInfo>, DbError> {
+    quote_identifier(table)?;
+    let (schema, table) = match table.split_once('.') {
+        Some((schema, table)) => (Some(schema), table),
+        None => (None, table),
+    };
+
+    let rows: Vec<(String, String, String, String, Option<String>, String)> = sqlx::query_as(
+        "SELECT CAST(COLUMN_NAME AS CHAR), CAST(DATA_TYPE AS CHAR), CAST(COLUMN_TYPE AS CHAR), \
+                CAST(IS_NULLABLE AS CHAR), CAST(COLUMN_DEFAULT AS CHAR), CAST(COLUMN_KEY AS CHAR) \
+         FROM information_schema.columns \
+         WHERE TABLE_SCHEMA = COALESCE(?, DATABASE()) AND TABLE_NAME = ? \
+         ORDER BY ORDINAL_POSITION",
+    )
+    .bind(schema)
+    .bind(table)
+    .fetch_all(get_db_pool())
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(
+            |(name, data_type, column_type, nullable, default, key)| ColumnInfo {
+                name,
+                data_type,
+                column_type,
+                nullable: nullable == "YES",
+                default,
+                key,
+            },
+        )
+        .collect())
+}
+
+/// Runs a DDL statement such as `ALTER TABLE`, waiting at most `lock_wait` for its metadata
+/// lock.
+///
+/// DDL needs an exclusive metadata lock on its table, which it cannot get while any open
+/// transaction has used the table, and MySQL's default `lock_wait_timeout` is a year. Worse,
+/// the waiting DDL blocks every later query on the table. This runs the statement on a pinned
+/// connection with `lock_wait_timeout` set to `lock_wait` (at least one second), restoring the
+/// default afterwards. If the default cannot be restored, the connection is closed instead of
+/// going back to the pool, and the statement's own result is still returned.
+///
+/// # Returns
+/// The number of affected rows reported by the server.
+///
+/// # Errors
+/// Returns [`DbError::MetadataLockTimeout`] if the lock wait timed out, listing the open
+/// transactions (see [`long_running_transactions`]) so the blocker can be found; any other
+/// failure is returned as [`DbError::Sqlx`].
+///
+/// # Example
+/// ```rust,no_run
+/// use std::time::Duration;
+/// use zirv_db_sqlx::{error::DbError, schema::execute_ddl};
+///
+/// # async fn example() -> Result<(), DbError> {
+/// match execute_ddl("ALTER TABLE users ADD COLUMN nickname VARCHAR(32)", Duration::from_secs(5)).await {
+///     Err(e @ DbError::MetadataLockTimeout { .. }) => eprintln!("migration blocked: {}", e),
+///     other => {
+///         other?;
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub async fn execute_ddl(sql: &str, lock_wait: Duration) -> Result<u64, DbError> {
+    let mut conn = acquire().await?;
+    sqlx::query("SET SESSION lock_wait_timeout = ?")
+        .bind(lock_wait.as_secs().max(1))
+        .execute(&mut *conn)
+        .await?;
+    let result = sqlx::raw_sql(sql).execute(&mut *conn).await;
+    // Best effort: the statement's own outcome matters more than the reset.
+    if let Err(e) = sqlx::query("SET SESSION lock_wait_timeout = DEFAULT")
+        .execute(&mut *conn)
+        .await
+    {
+        log_warn(format_args!(
+            "Failed to restore lock_wait_timeout after a DDL statement, closing the connection: {}",
+            format_db_error(&e)
+        ));
+        drop(conn.detach());
+    }
+
+    match result {
+        Ok(result) => Ok(result.rows_affected()),
+        Err(e) => {
+            Err(
+                classify_ddl_error(e, async || long_running_transactions(Duration::ZERO).await)
+                    .await,
+            )
+        }
+    }
+}
+
+/// Turns a failed DDL statement's error into a [`DbError`], looking up the blocking
+/// transactions with `find_blockers` if it was a metadata lock timeout.
+async fn classify_ddl_error<F>(err: sqlx::Error, find_blockers: F) -> DbError
+where
+    F: AsyncFnOnce() -> Result<Vec<LongTransaction>, sqlx::Error>,
+{
+    if !is_lock_wait_timeout(&err) {
+        return err.into();
+    }
+
+    let blockers = find_blockers().await.unwrap_or_else(|e| {
+        log_warn(format_args!(
+            "Failed to list the transactions blocking a DDL statement: {}",
+            format_db_error(&e)
+        ));
+        Vec::new()
+    });
+    DbError::MetadataLockTimeout {
+        blockers,
+        source: err,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{self, database_of};
+
+    fn schema_pools(max_pools: usize) -> SchemaPools {
+        let options = MySqlConnectOptions::new()
+            .host("localhost")
+            .database("base");
+        SchemaPools::new(options, MySqlPoolOptions::new(), max_pools)
+    }
+
+    #[tokio::test]
+    async fn test_pools_are_created_per_schema_and_cached() {
+        let pools = schema_pools(4);
+
+        let a = pools.get("schema_a").unwrap();
+        let b = pools.get("schema_b").unwrap();
+        assert_eq!(database_of(&a), "schema_a");
+        assert_eq!(database_of(&b), "schema_b");
+        assert_eq!(a.connect_options().get_host(), "localhost");
+        assert_eq!(pools.len(), 2);
+
+        pools.get("schema_a").unwrap();
+        assert_eq!(pools.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_least_recently_used_pool_is_evicted() {
+        let pools = schema_pools(2);
+        let first = pools.get("schema_a").unwrap();
+        pools.get("schema_b").unwrap();
+        pools.get("schema_a").unwrap();
+        pools.get("schema_c").unwrap();
+
+        assert_eq!(pools.len(), 2);
+        let state = pools.state.lock().unwrap();
+        assert!(state.pools.contains_key("schema_a"));
+        assert!(!state.pools.contains_key("schema_b"));
+        assert!(state.pools.contains_key("schema_c"));
+        drop(state);
+
+        // A handed-out pool keeps working after its eviction.
+        assert_eq!(database_of(&first), "schema_a");
+    }
+
+    #[tokio::test]
+    async fn test_invalid_schema_names_are_rejected() {
+        let pools = schema_pools(2);
+        assert!(matches!(
+            pools.get("a; DROP DATABASE b"),
+            Err(DbError::InvalidIdentifier(_))
+        ));
+        assert!(pools.get("schema_a.events").is_err());
+        assert!(pools.is_empty());
+    }
+
+    #[test]
+    fn test_queries_hit_the_requested_schema() {
+        test_util::run_with_db(|pool| async move {
+            for schema in ["zirv_test_schema_a", "zirv_test_schema_b"] {
+                sqlx::query(&format!("CREATE DATABASE IF NOT EXISTS {}", schema))
+                    .execute(pool)
+                    .await
+                    .unwrap();
+            }
+            let url = test_util::database_url().unwrap();
+            let options: MySqlConnectOptions = url.parse().unwrap();
+            let pools = SchemaPools::new(options, MySqlPoolOptions::new(), 4);
+
+            for schema in ["zirv_test_schema_a", "zirv_test_schema_b"] {
+                let current: String = sqlx::query_scalar("SELECT DATABASE()")
+                    .fetch_one(&pools.get(schema).unwrap())
+                    .await
+                    .unwrap();
+                assert_eq!(current, schema);
+            }
+
+            for schema in ["zirv_test_schema_a", "zirv_test_schema_b"] {
+                sqlx::query(&format!("DROP DATABASE {}", schema))
+                    .execute(pool)
+                    .await
+                    .unwrap();
+            }
+        });
+    }
+
+    #[test]
+    fn test_qualify_schema_quotes_and_validates_the_schema() {
+        assert_eq!(
+            qualify_schema(
+                "SELECT a.id FROM {schema}.a JOIN {schema}.b ON a.id = b.id",
+                "tenant_1"
+            )
+            .unwrap(),
+            "SELECT a.id FROM `tenant_1`.a JOIN `tenant_1`.b ON a.id = b.id"
+        );
+        // Only qualified names are rewritten.
+        assert_eq!(
+            qualify_schema("SELECT '{schema}' FROM {schema}.a", "tenant_1").unwrap(),
+            "SELECT '{schema}' FROM `tenant_1`.a"
+        );
+        assert!(matches!(
+            qualify_schema("SELECT * FROM {schema}.a", "x`; DROP DATABASE y; --"),
+            Err(DbError::InvalidIdentifier(_))
+        ));
+        assert!(qualify_schema("SELECT * FROM {schema}.a", "app.events").is_err());
+    }
+
+    #[test]
+    fn test_on_schema_reads_from_the_given_schema() {
+        test_util::run_with_db(|pool| async move {
+            for (schema, name) in [("zirv_test_on_a", "alpha"), ("zirv_test_on_b", "beta")] {
+                sqlx::query(&format!("CREATE DATABASE IF NOT EXISTS {}", schema))
+                    .execute(pool)
+                    .await
+                    .unwrap();
+                sqlx::query(&format!(
+                    "CREATE TABLE IF NOT EXISTS {}.items (id INT PRIMARY KEY, name VARCHAR(16))",
+                    schema
+                ))
+                .execute(pool)
+                .await
+                .unwrap();
+                sqlx::query(&format!("REPLACE INTO {}.items VALUES (1, ?)", schema))
+                    .bind(name)
+                    .execute(pool)
+                    .await
+                    .unwrap();
+            }
+
+            let sql = "SELECT name FROM {schema}.items WHERE id = ?";
+            let a: Vec<(String,)> = on_schema("zirv_test_on_a", sql, &[1.into()]).await.unwrap();
+            let b: Vec<(String,)> = on_schema("zirv_test_on_b", sql, &[1.into()]).await.unwrap();
+            assert_eq!(a, [("alpha".to_owned(),)]);
+            assert_eq!(b, [("beta".to_owned(),)]);
+
+            for schema in ["zirv_test_on_a", "zirv_test_on_b"] {
+                sqlx::query(&format!("DROP DATABASE {}", schema))
+                    .execute(pool)
+                    .await
+                    .unwrap();
+            }
+        });
+    }
+
+    #[test]
+    fn test_check_table_collations_reports_mismatches() {
+        test_util::run_with_db(|pool| async move {
+            sqlx::query("DROP TABLE IF EXISTS zirv_test_collation")
+                .execute(pool)
+                .await
+                .unwrap();
+            sqlx::query(
+                "CREATE TABLE zirv_test_collation (\
+                   id INT PRIMARY KEY, \
+                   good VARCHAR(16) COLLATE utf8mb4_unicode_ci, \
+                   drifted VARCHAR(16) CHARACTER SET latin1 COLLATE latin1_swedish_ci\
+                 ) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci",
+            )
+            .execute(pool)
+            .await
+            .unwrap();
+
+            // MySQL 8 reports these columns as binary strings, which only decode once cast.
+            let mismatches: Vec<_> = check_table_collations("utf8mb4_unicode_ci")
+                .await
+                .unwrap()
+                .into_iter()
+                .filter(|m| m.table == "zirv_test_collation")
+                .collect();
+            assert_eq!(
+                mismatches,
+                [CollationMismatch {
+                    table: "zirv_test_collation".to_owned(),
+                    column: Some("drifted".to_owned()),
+                    collation: "latin1_swedish_ci".to_owned(),
+                }]
+            );
+
+            let mismatches = check_table_collations("latin1_swedish_ci").await.unwrap();
+            assert!(mismatches.contains(&CollationMismatch {
+                table: "zirv_test_collation".to_owned(),
+                column: None,
+                collation: "utf8mb4_unicode_ci".to_owned(),
+            }));
+
+            sqlx::query("DROP TABLE zirv_test_collation")
+                .execute(pool)
+                .await
+                .unwrap();
+        });
+    }
+
+    #[tokio::test]
+    async fn test_describe_table_rejects_invalid_names() {
+        assert!(matches!(
+            describe_table("users; DROP TABLE users").await,
+            Err(DbError::InvalidIdentifier(_))
+        ));
+    }
+
+    #[test]
+    fn test_describe_table_lists_columns() {
+        test_util::run_with_db(|pool| async move {
+            sqlx::query("DROP TABLE IF EXISTS zirv_test_describe")
+                .execute(pool)
+                .await
+                .unwrap();
+            sqlx::query(
+                "CREATE TABLE zirv_test_describe (\
+                   id INT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY, \
+                   email VARCHAR(255) NOT NULL UNIQUE, \
+                   status VARCHAR(16) NOT NULL DEFAULT 'new', \
+                   note TEXT NULL\
+                 )",
+            )
+            .execute(pool)
+            .await
+            .unwrap();
+
+            let column =
+                |name: &str, data_type: &str, column_type: &str, nullable, key: &str| ColumnInfo {
+                    name: name.to_owned(),
+                    data_type: data_type.to_owned(),
+                    column_type: column_type.to_owned(),
+                    nullable,
+                    default: None,
+                    key: key.to_owned(),
+                };
+            let expected = vec![
+                column("id", "int", "int unsigned", false, "PRI"),
+                column("email", "varchar", "varchar(255)", false, "UNI"),
+                ColumnInfo {
+                    default: Some("new".to_owned()),
+                    ..column("status", "varchar", "varchar(16)", false, "")
+                },
+                column("note", "text", "text", true, ""),
+            ];
+            assert_eq!(
+                describe_table("zirv_test_describe").await.unwrap(),
+                expected
+            );
+
+            let database: String = sqlx::query_scalar("SELECT DATABASE()")
+                .fetch_one(pool)
+                .await
+                .unwrap();
+            let qualified = format!("{}.zirv_test_describe", database);
+            assert_eq!(describe_table(&qualified).await.unwrap(), expected);
+            assert!(
+                describe_table("zirv_test_missing")
+                    .await
+                    .unwrap()
+                    .is_empty()
+            );
+
+            sqlx::query("DROP TABLE zirv_test_describe")
+                .execute(pool)
+                .await
+                .unwrap();
+        });
+    }
+
+    #[tokio::test]
+    async fn test_metadata_lock_timeouts_are_classified_with_blockers() {
+        let blocker = LongTransaction {
+            connection_id: 42,
+            age: Duration::from_secs(310),
+            state: "RUNNING".to_owned(),
+            query: None,
+        };
+        let err = classify_ddl_error(
+            test_util::db_error(
+                1205,
+                "Lock wait timeout exceeded; try restarting transaction",
+            ),
+            async || Ok(vec![blocker.clone()]),
+        )
+        .await;
+        match &err {
+            DbError::MetadataLockTimeout { blockers, .. } => assert_eq!(blockers, &[blocker]),
+            other => panic!("expected MetadataLockTimeout, got {:?}", other),
+        }
+        assert_eq!(
+            err.to_string(),
+            "timed out waiting for a metadata lock; finish or KILL the blocking \
+             transaction(s): connection 42 (RUNNING, open for 310s)"
+        );
+
+        // Other errors are passed through without looking for blockers.
+        let err = classify_ddl_error(
+            test_util::db_error(1060, "Duplicate column name"),
+            async || panic!("blockers looked up for an unrelated error"),
+        )
+        .await;
+        assert!(matches!(err, DbError::Sqlx(_)));
+    }
+
+    #[test]
+    fn test_execute_ddl_reports_the_blocking_transaction() {
+        test_util::run_with_db(|pool| async move {
+            sqlx::query("DROP TABLE IF EXISTS zirv_test_ddl")
+                .execute(pool)
+                .await
+                .unwrap();
+            sqlx::query("CREATE TABLE zirv_test_ddl (id INT PRIMARY KEY) ENGINE = InnoDB")
+                .execute(pool)
+                .await
+                .unwrap();
+
+            // An open transaction that has read the table holds a metadata lock on it.
+            let mut tx = pool.begin().await.unwrap();
+            let blocker_id: u64 = sqlx::query_scalar("SELECT CONNECTION_ID()")
+                .fetch_one(&mut *tx)
+                .await
+                .unwrap();
+            sqlx::query("SELECT COUNT(*) FROM zirv_test_ddl")
+                .execute(&mut *tx)
+                .await
+                .unwrap();
+
+            let err = execute_ddl(
+                "ALTER TABLE zirv_test_ddl ADD COLUMN name VARCHAR(16)",
+                Duration::from_secs(1),
+            )
+            .await
+            .unwrap_err();
+            match err {
+                DbError::MetadataLockTimeout { blockers, .. } => {
+                    assert!(blockers.iter().any(|b| b.connection_id == blocker_id));
+                }
+                other => panic!("expected MetadataLockTimeout, got {:?}", other),
+            }
+
+            tx.rollback().await.unwrap();
+            execute_ddl(
+                "ALTER TABLE zirv_test_ddl ADD COLUMN name VARCHAR(16)",
+                Duration::from_secs(1),
+            )
+            .await
+            .unwrap();
+            sqlx::query("DROP TABLE zirv_test_ddl")
+                .execute(pool)
+                .await
+                .unwrap();
+        });
+    }
+}
@@ -0,0 +1,154 @@
+use std::fmt;
+
+use tokio::sync::OnceCell;
+
+use crate::db::get_db_pool;
+
+static SERVER_VERSION: OnceCell<ServerVersion> = OnceCell::const_new();
+
+/// The database server product behind a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ServerFlavor {
+    /// Oracle MySQL, or a compatible server that does not identify itself otherwise.
+    MySql,
+    /// MariaDB.
+    MariaDb,
+}
+
+/// A server's product and version, as reported by `SELECT VERSION()`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ServerVersion {
+    /// The server product.
+    pub flavor: ServerFlavor,
+    /// The major version, e.g. `8` for MySQL 8.0.36.
+    pub major: u32,
+    /// The minor version, e.g. `0` for MySQL 8.0.36.
+    pub minor: u32,
+    /// The patch version, e.g. `36` for MySQL 8.0.36.
+    pub patch: u32,
+}
+
+impl ServerVersion {
+    /// Parses a version string such as `8.0.36` or `10.11.6-MariaDB-1:10.11.6+maria~ubu2204`.
+    ///
+    /// MariaDB servers that prefix their version with `5.5.5-` for the sake of old replication
+    /// clients are recognized as well. Missing minor or patch numbers count as `0`.
+    ///
+    /// # Returns
+    /// `None` if the string does not start with a version number.
+    pub fn parse(version: &str) -> Option<Self> {
+        let flavor = if version.to_ascii_lowercase().contains("mariadb") {
+            ServerFlavor::MariaDb
+        } else {
+            ServerFlavor::MySql
+        };
+        let version = match flavor {
+            ServerFlavor::MariaDb => version.strip_prefix("5.5.5-").unwrap_or(version),
+            ServerFlavor::MySql => version,
+        };
+
+        let number = version
+            .split(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .next()
+            .unwrap_or("");
+        let mut parts = number.split('.').map(str::parse::<u32>);
+        let major = parts.next()?.ok()?;
+        let minor = parts.next().and_then(Result::ok).unwrap_or(0);
+        let patch = parts.next().and_then(Result::ok).unwrap_or(0);
+        Some(ServerVersion {
+            flavor,
+            major,
+            minor,
+            patch,
+        })
+    }
+
+    /// Returns `true` if this is MariaDB of at least the given version.
+    pub fn is_mariadb_at_least(&self, major: u32, minor: u32) -> bool {
+        self.flavor == ServerFlavor::MariaDb && (self.major, self.minor) >= (major, minor)
+    }
+
+    /// Returns `true` if the server supports `INSERT ... RETURNING` (MariaDB 10.5 and later).
+    pub fn supports_insert_returning(&self) -> bool {
+        self.is_mariadb_at_least(10, 5)
+    }
+}
+
+impl fmt::Display for ServerVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let product = match self.flavor {
+            ServerFlavor::MySql => "MySQL",
+            ServerFlavor::MariaDb => "MariaDB",
+        };
+        write!(
+            f,
+            "{} {}.{}.{}",
+            product, self.major, self.minor, self.patch
+        )
+    }
+}
+
+/// Returns the version of the server behind the global pool.
+///
+/// The version is queried once and cached for the lifetime of the process.
+///
+/// # Errors
+/// Returns the query's error, or [`sqlx::Error::Protocol`] if the server reports a version that
+/// cannot be parsed.
+pub async fn server_version() -> Result<&'static ServerVersion, sqlx::Error> {
+    SERVER_VERSION
+        .get_or_try_init(async || {
+            let version: String = sqlx::query_scalar("SELECT VERSION()")
+                .fetch_one(get_db_pool())
+                .await?;
+            ServerVersion::parse(&version).ok_or_else(|| {
+                sqlx::Error::Protocol(format!("unrecognized server version {:?}", version))
+            })
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    #[test]
+    fn test_parse_mysql_versions() {
+        let version = ServerVersion::parse("8.0.36").unwrap();
+        assert_eq!(version.flavor, ServerFlavor::MySql);
+        assert_eq!((version.major, version.minor, version.patch), (8, 0, 36));
+        assert_eq!(version.to_string(), "MySQL 8.0.36");
+
+        let version = ServerVersion::parse("5.7.44-log").unwrap();
+        assert_eq!((version.major, version.minor, version.patch), (5, 7, 44));
+        assert!(!version.supports_insert_returning());
+        assert_eq!(ServerVersion::parse("unknown"), None);
+    }
+
+    #[test]
+    fn test_parse_mariadb_versions() {
+        let version = ServerVersion::parse("10.11.6-MariaDB-1:10.11.6+maria~ubu2204").unwrap();
+        assert_eq!(version.flavor, ServerFlavor::MariaDb);
+        assert_eq!((version.major, version.minor, version.patch), (10, 11, 6));
+        assert!(version.supports_insert_returning());
+
+        let version = ServerVersion::parse("5.5.5-10.4.32-MariaDB").unwrap();
+        assert_eq!((version.major, version.minor, version.patch), (10, 4, 32));
+        assert!(!version.supports_insert_returning());
+        assert!(
+            ServerVersion::parse("10.5.0-MariaDB")
+                .unwrap()
+                .supports_insert_returning()
+        );
+    }
+
+    #[test]
+    fn test_server_version_against_database() {
+        test_util::run_with_db(|_| async {
+            let version = server_version().await.unwrap();
+            assert!(version.major >= 5);
+            assert!(std::ptr::eq(version, server_version().await.unwrap()));
+        });
+    }
+}
@@ -0,0 +1,254 @@
+use std::future::Future;
+
+use futures_util::future::try_join_all;
+use serde_json::Value;
+use sqlx::{MySql, Pool};
+use zirv_config::read_config;
+
+use crate::{
+    config::{DATABASE_URL_ENV, PoolConfig, get_string},
+    db::connect,
+    error::DbError,
+};
+
+/// Maps a shard key to a shard index, given the number of shards.
+pub type ShardFn = fn(key: u64, shard_count: usize) -> usize;
+
+/// The default shard function: `key % shard_count`.
+pub fn modulo_shard(key: u64, shard_count: usize) -> usize {
+    (key % shard_count as u64) as usize
+}
+
+/// A set of pools, one per physical database, with keys routed to shards by a shard function.
+///
+/// Every key is always routed to the same pool, so all data for a key lives on one shard.
+///
+/// # Cross-shard limitations
+/// A transaction started on a shard's pool only covers that shard. There is no atomicity across
+/// shards: an operation touching several shards (for example through [`on_all_shards`]) runs as
+/// independent statements or transactions, and a failure on one shard does not undo the work
+/// already done on the others.
+///
+/// [`on_all_shards`]: ShardedPool::on_all_shards
+#[derive(Debug, Clone)]
+pub struct ShardedPool {
+    pools: Vec<Pool<MySql>>,
+    shard_fn: ShardFn,
+}
+
+impl ShardedPool {
+    /// Creates a sharded pool from existing pools, using [`modulo_shard`] for routing.
+    ///
+    /// The position of each pool in `pools` is its shard index.
+    ///
+    /// # Panics
+    /// Panics if `pools` is empty.
+    pub fn new(pools: Vec<Pool<MySql>>) -> Self {
+        assert!(!pools.is_empty(), "A ShardedPool needs at least one shard.");
+        ShardedPool {
+            pools,
+            shard_fn: modulo_shard,
+        }
+    }
+
+    /// Replaces the function used to route keys to shards.
+    ///
+    /// Indices returned by `shard_fn` are reduced modulo the shard count, so a shard function can
+    /// never route a key outside of the available shards.
+    pub fn with_shard_fn(mut self, shard_fn: ShardFn) -> Self {
+        self.shard_fn = shard_fn;
+        self
+    }
+
+    /// Connects to every shard listed in the `database.shards` configuration array.
+    ///
+    /// Each entry is a connection URL. Every shard pool is built like the global pool, with the
+    /// same `database` settings (pool size and timeouts, `ssl_mode`, `force_utc`, statement
+    /// cache, validation query, ...) applied to the shard's URL. Neither `database.url` nor
+    /// `DATABASE_URL` needs to be set.
+    ///
+    /// # Errors
+    /// Returns [`DbError::Config`] if `database.shards` is missing, empty or not an array of
+    /// strings or if another `database` setting is invalid, and [`DbError::Sqlx`] if connecting
+    /// to a shard fails.
+    pub async fn from_config() -> Result<Self, DbError> {
+        let urls = shard_urls(&read_config!("database.shards").unwrap_or(Value::Null))?;
+        let value = read_config!("database").unwrap_or(Value::Null);
+        let env_url = std::env::var(DATABASE_URL_ENV).ok();
+        let config = shard_config(&value, env_url.as_deref(), &urls[0])?;
+
+        let mut pools = Vec::with_capacity(urls.len());
+        for url in &urls {
+            pools.push(connect(&config, url).await?);
+        }
+        Ok(ShardedPool::new(pools))
+    }
+
+    /// Returns the number of shards.
+    pub fn shard_count(&self) -> usize {
+        self.pools.len()
+    }
+
+    /// Returns the index of the shard that `key` is routed to.
+    pub fn shard_index(&self, key: u64) -> usize {
+        (self.shard_fn)(key, self.pools.len()) % self.pools.len()
+    }
+
+    /// Returns the pool of the shard that `key` is routed to.
+    pub fn get_shard(&self, key: u64) -> &Pool<MySql> {
+        &self.pools[self.shard_index(key)]
+    }
+
+    /// Returns all shard pools, ordered by shard index.
+    pub fn shards(&self) -> &[Pool<MySql>] {
+        &self.pools
+    }
+
+    /// Runs `f` against every shard concurrently and collects the results in shard order.
+    ///
+    /// Fails with the first error encountered. Work already done on other shards is not rolled
+    /// back (see the cross-shard limitations above).
+    pub async fn on_all_shards<'a, F, Fut, T>(&'a self, f: F) -> Result<Vec<T>, sqlx::Error>
+    where
+        F: Fn(&'a Pool<MySql>) -> Fut,
+        Fut: Future<Output = Result<T, sqlx::Error>>,
+    {
+        try_join_all(self.pools.iter().map(f)).await
+    }
+}
+
+/// Resolves the settings to build shard pools with from the `database` configuration value,
+/// using `shard_url` as the connection URL if none is configured.
+fn shard_config(
+    value: &Value,
+    env_url: Option<&str>,
+    shard_url: &str,
+) -> Result<PoolConfig, DbError> {
+    let env_url = match get_string(value, "url")? {
+        Some(_) => env_url,
+        None => env_url.or(Some(shard_url)),
+    };
+    PoolConfig::from_sources(value, env_url)
+}
+
+/// Parses the `database.shards` configuration value.
+fn shard_urls(value: &Value) -> Result<Vec<String>, DbError> {
+    let invalid =
+        || DbError::Config("database.shards must be a non-empty array of URLs".to_owned());
+
+    let urls = value
+        .as_array()
+        .ok_or_else(invalid)?
+        .iter()
+        .map(|url| url.as_str().map(str::to_owned).ok_or_else(invalid))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if urls.is_empty() {
+        return Err(invalid());
+    }
+    Ok(urls)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{database_of, lazy_pool};
+    use serde_json::json;
+
+    fn lazy_shards(count: usize) -> ShardedPool {
+        let pools = (0..count)
+            .map(|i| lazy_pool(&format!("shard_{}", i)))
+            .collect();
+        ShardedPool::new(pools)
+    }
+
+    #[tokio::test]
+    async fn test_get_shard_routes_by_modulo() {
+        let sharded = lazy_shards(3);
+
+        assert_eq!(sharded.shard_count(), 3);
+        assert_eq!(database_of(sharded.get_shard(0)), "shard_0");
+        assert_eq!(database_of(sharded.get_shard(4)), "shard_1");
+        assert_eq!(database_of(sharded.get_shard(42)), "shard_0");
+        assert_eq!(database_of(sharded.get_shard(u64::MAX)), "shard_0");
+    }
+
+    #[tokio::test]
+    async fn test_placement_is_consistent() {
+        let sharded = lazy_shards(4);
+        for key in 0..1_000 {
+            let first = sharded.shard_index(key);
+            assert_eq!(first, sharded.shard_index(key));
+            assert_eq!(
+                database_of(sharded.get_shard(key)),
+                format!("shard_{}", first)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_custom_shard_fn_is_kept_in_range() {
+        let sharded = lazy_shards(2).with_shard_fn(|key, _| (key / 100) as usize);
+
+        assert_eq!(sharded.shard_index(99), 0);
+        assert_eq!(sharded.shard_index(150), 1);
+        assert_eq!(sharded.shard_index(250), 0);
+    }
+
+    #[tokio::test]
+    async fn test_on_all_shards_visits_every_shard_in_order() {
+        let sharded = lazy_shards(3);
+        let databases = sharded
+            .on_all_shards(|pool| async move { Ok(database_of(pool)) })
+            .await
+            .unwrap();
+        assert_eq!(databases, ["shard_0", "shard_1", "shard_2"]);
+    }
+
+    #[test]
+    fn test_shard_config_uses_database_settings_or_defaults() {
+        let configured = json!({
+            "url": "mysql://primary/db",
+            "max_connections": 3,
+            "force_utc": true,
+        });
+        let config = shard_config(&configured, None, "mysql://a/db").unwrap();
+        assert_eq!(config.max_connections, 3);
+        assert!(config.force_utc);
+
+        // Without a database URL, the settings still apply.
+        let config = shard_config(&json!({ "max_connections": 4 }), None, "mysql://a/db").unwrap();
+        assert_eq!(config.max_connections, 4);
+
+        let config = shard_config(&Value::Null, None, "mysql://a/db").unwrap();
+        assert_eq!(
+            config.max_connections,
+            crate::config::DEFAULT_MAX_CONNECTIONS
+        );
+        assert!(!config.force_utc);
+    }
+
+    #[test]
+    fn test_shard_config_rejects_invalid_settings() {
+        for value in [
+            json!({ "max_connections": "many" }),
+            json!({ "url": "mysql://primary/db", "max_connections": -1 }),
+        ] {
+            assert!(matches!(
+                shard_config(&value, None, "mysql://a/db"),
+                Err(DbError::Config(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_shard_urls_parsing() {
+        assert_eq!(
+            shard_urls(&json!(["mysql://a/db", "mysql://b/db"])).unwrap(),
+            ["mysql://a/db", "mysql://b/db"]
+        );
+        assert!(shard_urls(&json!([])).is_err());
+        assert!(shard_urls(&json!("mysql://a/db")).is_err());
+        assert!(shard_urls(&json!([1, 2])).is_err());
+    }
+}
@@ -0,0 +1,210 @@
+use serde_json::Value;
+use sqlx::Arguments;
+use sqlx::mysql::MySqlArguments;
+
+use crate::error::DbError;
+
+/// Maximum length of a MySQL identifier.
+const MAX_IDENTIFIER_LEN: usize = 64;
+
+/// The SQL dialect used to quote identifiers.
+///
+/// The crate only talks to MySQL, so its own helpers always quote for [`Backend::MySql`];
+/// [`quote_identifier_for`] can quote for PostgreSQL when building SQL for another client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// MySQL and MariaDB, which quote identifiers with backticks.
+    MySql,
+    /// PostgreSQL, which quotes identifiers with double quotes.
+    Postgres,
+}
+
+impl Backend {
+    /// The backend all dynamic-SQL helpers of this crate quote for.
+    ///
+    /// This is always [`Backend::MySql`]: there is no PostgreSQL support, and no feature to
+    /// select another backend.
+    pub const COMPILED: Backend = Backend::MySql;
+
+    /// Returns the character used to quote identifiers.
+    pub const fn quote_char(self) -> char {
+        match self {
+            Backend::MySql => '`',
+            Backend::Postgres => '"',
+        }
+    }
+}
+
+/// Validates a table or column name and quotes it for use in dynamically built SQL.
+///
+/// Quotes for [`Backend::COMPILED`]; see [`quote_identifier_for`] for the validation rules.
+///
+/// # Example
+/// ```rust
+/// use zirv_db_sqlx::sql::quote_identifier;
+///
+/// assert_eq!(quote_identifier("users").unwrap(), "`users`");
+/// assert_eq!(quote_identifier("reporting.events").unwrap(), "`reporting`.`events`");
+/// assert!(quote_identifier("users; DROP TABLE users").is_err());
+/// ```
+pub fn quote_identifier(ident: &str) -> Result<String, DbError> {
+    quote_identifier_for(Backend::COMPILED, ident)
+}
+
+/// Validates a table or column name and quotes it for the given backend.
+///
+/// The name may be qualified with one `.` (`schema.table` or `table.column`), in which case
+/// each part is validated and quoted separately. Every part must be a non-empty identifier of
+/// at most 64 ASCII letters, digits, `_` or `$`, which rules out any attempt to smuggle SQL
+/// through a name; names with more than two parts are rejected.
+pub fn quote_identifier_for(backend: Backend, ident: &str) -> Result<String, DbError> {
+    let parts: Vec<&str> = ident.split('.').collect();
+    if parts.len() > 2 {
+        return Err(DbError::InvalidIdentifier(ident.to_owned()));
+    }
+
+    let quoted = parts
+        .into_iter()
+        .map(|part| {
+            quote_part(backend, part).ok_or_else(|| DbError::InvalidIdentifier(ident.to_owned()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(quoted.join("."))
+}
+
+/// Validates and quotes a name that must not be qualified, such as a schema name.
+pub(crate) fn quote_unqualified_identifier(ident: &str) -> Result<String, DbError> {
+    quote_part(Backend::COMPILED, ident).ok_or_else(|| DbError::InvalidIdentifier(ident.to_owned()))
+}
+
+/// Quotes a single identifier part, or returns `None` if it is not valid.
+fn quote_part(backend: Backend, part: &str) -> Option<String> {
+    let valid = !part.is_empty()
+        && part.len() <= MAX_IDENTIFIER_LEN
+        && part
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$');
+
+    valid.then(|| {
+        let quote = backend.quote_char();
+        format!("{}{}{}", quote, part, quote)
+    })
+}
+
+/// Builds query arguments from a list of JSON values.
+///
+/// This is how the helpers that accept dynamic bind values (`&[serde_json::Value]`) bind them:
+/// `null` binds SQL `NULL`, booleans and numbers bind as `BOOLEAN`, `BIGINT` (signed or unsigned)
+/// or `DOUBLE`, strings bind as text, and arrays and objects bind as their JSON text.
+pub fn json_arguments(values: &[Value]) -> Result<MySqlArguments, DbError> {
+    let mut args = MySqlArguments::default();
+    for value in values {
+        let added = match value {
+            Value::Null => args.add(None::<String>),
+            Value::Bool(b) => args.add(*b),
+            Value::Number(n) => match (n.as_i64(), n.as_u64()) {
+                (Some(i), _) => args.add(i),
+                (None, Some(u)) => args.add(u),
+                _ => args.add(n.as_f64()),
+            },
+            Value::String(s) => args.add(s.clone()),
+            Value::Array(_) | Value::Object(_) => args.add(value.to_string()),
+        };
+        added.map_err(sqlx::Error::Encode)?;
+    }
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote_identifier_accepts_plain_names() {
+        assert_eq!(quote_identifier("users").unwrap(), "`users`");
+        assert_eq!(quote_identifier("created_at").unwrap(), "`created_at`");
+    }
+
+    #[test]
+    fn test_quote_identifier_accepts_qualified_names() {
+        assert_eq!(
+            quote_identifier("reporting.events").unwrap(),
+            "`reporting`.`events`"
+        );
+        assert_eq!(
+            quote_identifier_for(Backend::Postgres, "reporting.events").unwrap(),
+            "\"reporting\".\"events\""
+        );
+        assert!(quote_unqualified_identifier("reporting").is_ok());
+        assert!(quote_unqualified_identifier("reporting.events").is_err());
+    }
+
+    #[test]
+    fn test_quote_identifier_rejects_over_qualified_names() {
+        for ident in [
+            "a.b.c",
+            "db.schema.table.column",
+            "a..b",
+            ".events",
+            "reporting.",
+        ] {
+            assert!(
+                matches!(quote_identifier(ident), Err(DbError::InvalidIdentifier(ref i)) if i == ident),
+                "{:?} should be rejected",
+                ident
+            );
+        }
+    }
+
+    #[test]
+    fn test_quote_identifier_per_backend() {
+        assert_eq!(
+            quote_identifier_for(Backend::MySql, "users").unwrap(),
+            "`users`"
+        );
+        assert_eq!(
+            quote_identifier_for(Backend::Postgres, "users").unwrap(),
+            "\"users\""
+        );
+        assert_eq!(Backend::COMPILED, Backend::MySql);
+        assert!(quote_identifier_for(Backend::Postgres, "users\"").is_err());
+    }
+
+    #[test]
+    fn test_json_arguments_binds_every_value() {
+        use serde_json::json;
+
+        let values = [
+            json!(null),
+            json!(true),
+            json!(-1),
+            json!(u64::MAX),
+            json!(1.5),
+            json!("text"),
+            json!({ "a": [1, 2] }),
+        ];
+        assert_eq!(json_arguments(&values).unwrap().len(), values.len());
+    }
+
+    #[test]
+    fn test_quote_identifier_rejects_injection() {
+        for ident in [
+            "",
+            "users`",
+            "users; DROP TABLE users",
+            "a b",
+            "name--",
+            "reporting.events`; DROP TABLE users",
+            "reporting`.`events",
+            "reporting.`events`",
+            "x.users; --",
+        ] {
+            assert!(
+                matches!(quote_identifier(ident), Err(DbError::InvalidIdentifier(_))),
+                "{:?} should be rejected",
+                ident
+            );
+        }
+        assert!(quote_identifier(&"a".repeat(65)).is_err());
+    }
+}
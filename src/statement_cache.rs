@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+use crate::config::resolve_pool_config;
+
+static TRACKER: OnceLock<StatementCacheTracker> = OnceLock::new();
+
+/// Statistics about prepared statement reuse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Number of distinct statements currently tracked.
+    pub size: usize,
+    /// Maximum number of statements kept per connection (`database.statement_cache_capacity`).
+    pub capacity: usize,
+    /// Executions of a statement that was already cached.
+    pub hits: u64,
+    /// Executions of a statement that had to be prepared.
+    pub misses: u64,
+}
+
+#[derive(Debug, Default)]
+struct TrackerState {
+    /// Statement SQL mapped to the tick it was last used at.
+    statements: HashMap<String, u64>,
+    tick: u64,
+    hits: u64,
+    misses: u64,
+}
+
+/// Approximates the statement cache of a connection by replaying executed SQL through an LRU
+/// cache of the same capacity.
+///
+/// sqlx keeps a statement cache in every connection but does not expose its contents or hit
+/// rate, so this models a single cache shared by the whole pool. With several connections the
+/// real caches each see only part of the traffic, so the tracked hit rate is an upper bound that
+/// is exact for a single connection.
+#[derive(Debug)]
+pub struct StatementCacheTracker {
+    capacity: usize,
+    state: Mutex<TrackerState>,
+}
+
+impl StatementCacheTracker {
+    /// Creates a tracker modelling a cache of `capacity` statements.
+    pub fn new(capacity: usize) -> Self {
+        StatementCacheTracker {
+            capacity,
+            state: Mutex::new(TrackerState::default()),
+        }
+    }
+
+    /// Records an execution of `sql`, returning `true` if it was a cache hit.
+    pub fn track(&self, sql: &str) -> bool {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.tick += 1;
+        let tick = state.tick;
+
+        if let Some(last_used) = state.statements.get_mut(sql) {
+            *last_used = tick;
+            state.hits += 1;
+            return true;
+        }
+
+        state.misses += 1;
+        if self.capacity == 0 {
+            return false;
+        }
+        if state.statements.len() >= self.capacity {
+            let oldest = state
+                .statements
+                .iter()
+                .min_by_key(|(_, last_used)| **last_used)
+                .map(|(sql, _)| sql.clone());
+            if let Some(oldest) = oldest {
+                state.statements.remove(&oldest);
+            }
+        }
+        state.statements.insert(sql.to_owned(), tick);
+        false
+    }
+
+    /// Returns the current statistics.
+    pub fn stats(&self) -> CacheStats {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        CacheStats {
+            size: state.statements.len(),
+            capacity: self.capacity,
+            hits: state.hits,
+            misses: state.misses,
+        }
+    }
+}
+
+fn global_tracker() -> &'static StatementCacheTracker {
+    TRACKER.get_or_init(|| {
+        StatementCacheTracker::new(
+            resolve_pool_config().map_or(crate::config::DEFAULT_STATEMENT_CACHE_CAPACITY, |c| {
+                c.statement_cache_capacity
+            }),
+        )
+    })
+}
+
+/// Records an execution of `sql` by one of the crate's helpers.
+pub(crate) fn track(sql: &str) {
+    global_tracker().track(sql);
+}
+
+/// Returns statement cache statistics for the queries run through this crate's helpers.
+///
+/// See [`StatementCacheTracker`] for how the numbers are approximated. Use them to size
+/// `database.statement_cache_capacity`: many misses with a full cache suggest the capacity is
+/// too small for the application's working set of statements.
+pub fn statement_cache_stats() -> CacheStats {
+    global_tracker().stats()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repeated_queries_increase_hits() {
+        let tracker = StatementCacheTracker::new(10);
+        assert!(!tracker.track("SELECT * FROM users WHERE id = ?"));
+        for _ in 0..3 {
+            assert!(tracker.track("SELECT * FROM users WHERE id = ?"));
+        }
+        tracker.track("SELECT 1");
+
+        assert_eq!(
+            tracker.stats(),
+            CacheStats {
+                size: 2,
+                capacity: 10,
+                hits: 3,
+                misses: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn test_least_recently_used_statement_is_evicted() {
+        let tracker = StatementCacheTracker::new(2);
+        tracker.track("a");
+        tracker.track("b");
+        tracker.track("a");
+        tracker.track("c"); // evicts "b"
+
+        assert!(tracker.track("a"));
+        assert!(!tracker.track("b"));
+        assert_eq!(tracker.stats().size, 2);
+    }
+}
@@ -0,0 +1,350 @@
+use futures_util::{Stream, TryStreamExt, pin_mut};
+use sqlx::FromRow;
+use sqlx::mysql::MySqlRow;
+
+use crate::db::get_db_pool;
+use crate::error::DbError;
+#[cfg(feature = "serde")]
+use {
+    crate::{row::row_to_json, sql::json_arguments},
+    bytes::Bytes,
+    futures_util::{StreamExt, future, stream},
+    serde_json::{Map, Value},
+};
+
+/// Streams the rows of a query and returns the first one matching `predicate`.
+///
+/// Rows are decoded and tested one at a time as they arrive. As soon as a row matches, the
+/// stream is dropped and the connection goes back to the pool without the remaining rows being
+/// decoded; the server may still have to finish sending the result set, so for large tables an
+/// additional `WHERE`/`LIMIT` remains the cheapest filter.
+///
+/// # Returns
+/// `Ok(None)` if no row matches.
+///
+/// # Example
+/// ```rust,no_run
+/// use zirv_db_sqlx::stream::find_streaming;
+///
+/// #[derive(sqlx::FromRow)]
+/// struct Document {
+///     id: i64,
+///     body: String,
+/// }
+///
+/// async fn first_mention(word: &str) -> Result<Option<Document>, sqlx::Error> {
+///     find_streaming("SELECT id, body FROM documents ORDER BY id", |doc: &Document| {
+///         doc.body.contains(word)
+///     })
+///     .await
+/// }
+/// ```
+pub async fn find_streaming<T, F>(sql: &str, predicate: F) -> Result<Option<T>, sqlx::Error>
+where
+    T: for<'r> FromRow<'r, MySqlRow> + Send + Unpin,
+    F: FnMut(&T) -> bool,
+{
+    crate::statement_cache::track(sql);
+    #[cfg(feature = "query-recorder")]
+    let started = std::time::Instant::now();
+    let found = first_match(sqlx::query_as::<_, T>(sql).fetch(get_db_pool()), predicate).await?;
+    #[cfg(feature = "query-recorder")]
+    crate::recorder::record_values(sql, &[], started, found.is_some() as u64);
+    Ok(found)
+}
+
+/// Returns the first item of `stream` matching `predicate`, without polling any further.
+async fn first_match<S, T, E, F>(stream: S, mut predicate: F) -> Result<Option<T>, E>
+where
+    S: Stream<Item = Result<T, E>>,
+    F: FnMut(&T) -> bool,
+{
+    pin_mut!(stream);
+    while let Some(item) = stream.try_next().await? {
+        if predicate(&item) {
+            return Ok(Some(item));
+        }
+    }
+    Ok(None)
+}
+
+/// Runs a query that must return exactly one row and decodes it.
+///
+/// Useful for invariants such as a settings table with a single row. At most two rows are
+/// read: the second one is enough to know the invariant is broken.
+///
+/// # Errors
+/// Returns [`DbError::NotFound`] if the query returns no row and [`DbError::TooManyRows`] if it
+/// returns more than one.
+///
+/// # Example
+/// ```rust,no_run
+/// use zirv_db_sqlx::stream::fetch_exactly_one;
+///
+/// # async fn example() -> Result<(), zirv_db_sqlx::error::DbError> {
+/// let (maintenance,): (bool,) = fetch_exactly_one("SELECT maintenance FROM settings").await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn fetch_exactly_one<T>(sql: &str) -> Result<T, DbError>
+where
+    T: for<'r> FromRow<'r, MySqlRow> + Send + Unpin,
+{
+    crate::statement_cache::track(sql);
+    #[cfg(feature = "query-recorder")]
+    let started = std::time::Instant::now();
+    let row = exactly_one(sqlx::query_as::<_, T>(sql).fetch(get_db_pool())).await?;
+    #[cfg(feature = "query-recorder")]
+    crate::recorder::record_values(sql, &[], started, 1);
+    Ok(row)
+}
+
+/// Returns the only item of `stream`, reading at most two items.
+async fn exactly_one<S, T>(stream: S) -> Result<T, DbError>
+where
+    S: Stream<Item = Result<T, sqlx::Error>>,
+{
+    pin_mut!(stream);
+    let first = stream.try_next().await?.ok_or(DbError::NotFound)?;
+    match stream.try_next().await? {
+        None => Ok(first),
+        Some(_) => Err(DbError::TooManyRows { count: 2 }),
+    }
+}
+
+/// Streams the rows of a query as newline-delimited JSON (requires the `serde` feature).
+///
+/// Every row is converted with [`row_to_json`] and yielded as one JSON object followed by `\n`,
+/// as soon as it is read, so large exports are never buffered in memory. Bind values are bound
+/// as described in [`json_arguments`]. The stream can be used directly as a streaming HTTP body,
+/// e.g. with axum's `Body::from_stream`.
+///
+/// Errors are reported per item: a row that cannot be read or serialized yields an `Err`, and
+/// the consumer decides whether to stop. Invalid bind values yield a single `Err`.
+///
+/// # Example
+/// ```rust,no_run
+/// use futures_util::TryStreamExt;
+/// use serde_json::json;
+/// use zirv_db_sqlx::stream::stream_ndjson;
+///
+/// # async fn example() -> Result<(), zirv_db_sqlx::error::DbError> {
+/// let binds = [json!(2024)];
+/// let mut lines = stream_ndjson("SELECT * FROM orders WHERE year = ?", &binds);
+/// while let Some(line) = lines.try_next().await? {
+///     // Write `line` to the response body.
+/// #   let _ = line;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "serde")]
+pub fn stream_ndjson<'a>(
+    sql: &'a str,
+    binds: &[Value],
+) -> impl Stream<Item = Result<Bytes, DbError>> + Send + 'a {
+    match json_arguments(binds) {
+        Ok(args) => {
+            crate::statement_cache::track(sql);
+            #[cfg(feature = "query-recorder")]
+            let mut recording = crate::recorder::StreamRecording::start(sql, binds);
+            let rows = sqlx::query_with(sql, args)
+                .fetch(get_db_pool())
+                .map(move |row| {
+                    #[cfg(feature = "query-recorder")]
+                    if row.is_ok() {
+                        recording.row();
+                    }
+                    row.map(|row| row_to_json(&row)).map_err(DbError::from)
+                });
+            encode_ndjson(rows).left_stream()
+        }
+        Err(e) => stream::once(future::ready(Err(e))).right_stream(),
+    }
+}
+
+/// Serializes each row of `rows` to a JSON line.
+#[cfg(feature = "serde")]
+fn encode_ndjson<S>(rows: S) -> impl Stream<Item = Result<Bytes, DbError>>
+where
+    S: Stream<Item = Result<Map<String, Value>, DbError>>,
+{
+    rows.map(|row| {
+        let mut line = serde_json::to_vec(&row?)
+            .map_err(|e| DbError::Sqlx(sqlx::Error::Decode(Box::new(e))))?;
+        line.push(b'\n');
+        Ok(Bytes::from(line))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+    use futures_util::{StreamExt, stream};
+    use std::cell::Cell;
+
+    #[tokio::test]
+    async fn test_first_match_stops_reading_at_the_match() {
+        let read = Cell::new(0);
+        let rows = stream::iter(1..=100)
+            .inspect(|_| read.set(read.get() + 1))
+            .map(Ok::<_, sqlx::Error>);
+
+        let found = first_match(rows, |n| n % 7 == 0).await.unwrap();
+
+        assert_eq!(found, Some(7));
+        assert_eq!(read.get(), 7);
+    }
+
+    #[tokio::test]
+    async fn test_first_match_without_match() {
+        let rows = stream::iter(1..=10).map(Ok::<_, sqlx::Error>);
+        assert_eq!(first_match(rows, |n| *n > 10).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_first_match_propagates_errors() {
+        let rows = stream::iter([Ok(1), Err(sqlx::Error::RowNotFound), Ok(3)]);
+        assert!(first_match(rows, |n| *n == 3).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_exactly_one_with_zero_one_and_many_rows() {
+        let none = stream::iter(Vec::<Result<i32, sqlx::Error>>::new());
+        assert!(matches!(exactly_one(none).await, Err(DbError::NotFound)));
+
+        let one = stream::iter([Ok::<_, sqlx::Error>(42)]);
+        assert_eq!(exactly_one(one).await.unwrap(), 42);
+
+        let read = Cell::new(0);
+        let many = stream::iter(1..=100)
+            .inspect(|_| read.set(read.get() + 1))
+            .map(Ok::<_, sqlx::Error>);
+        assert!(matches!(
+            exactly_one(many).await,
+            Err(DbError::TooManyRows { count: 2 })
+        ));
+        assert_eq!(read.get(), 2);
+    }
+
+    #[cfg(feature = "serde")]
+    #[tokio::test]
+    async fn test_encode_ndjson_yields_one_line_per_row() {
+        let rows = (1..=3).map(|id| {
+            let serde_json::Value::Object(row) = serde_json::json!({ "id": id, "name": "x" })
+            else {
+                unreachable!()
+            };
+            Ok(row)
+        });
+        let chunks: Vec<_> = encode_ndjson(stream::iter(rows)).collect().await;
+
+        let body: Vec<u8> = chunks
+            .into_iter()
+            .flat_map(|chunk| chunk.unwrap().to_vec())
+            .collect();
+        let body = String::from_utf8(body).unwrap();
+        assert!(body.ends_with('\n'));
+        let lines: Vec<serde_json::Value> = body
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[2], serde_json::json!({ "id": 3, "name": "x" }));
+    }
+
+    #[cfg(feature = "serde")]
+    #[tokio::test]
+    async fn test_encode_ndjson_reports_errors_per_row() {
+        let rows = vec![
+            Ok(Map::new()),
+            Err(DbError::Sqlx(sqlx::Error::RowNotFound)),
+            Ok(Map::new()),
+        ];
+        let chunks: Vec<_> = encode_ndjson(stream::iter(rows)).collect().await;
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks[0].is_ok() && chunks[1].is_err() && chunks[2].is_ok());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_stream_ndjson_against_database() {
+        test_util::run_with_db(|_| async {
+            let binds = [serde_json::json!(4)];
+            let chunks: Vec<Bytes> = stream_ndjson(
+                "WITH RECURSIVE n (i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < ?) \
+                 SELECT i, CONCAT('row ', i) AS label FROM n",
+                &binds,
+            )
+            .try_collect()
+            .await
+            .unwrap();
+
+            assert_eq!(chunks.len(), 4);
+            let first: serde_json::Value = serde_json::from_slice(&chunks[0]).unwrap();
+            assert_eq!(first, serde_json::json!({ "i": 1, "label": "row 1" }));
+        });
+    }
+
+    #[test]
+    fn test_fetch_exactly_one_against_database() {
+        test_util::run_with_db(|_| async {
+            let (one,): (i64,) = fetch_exactly_one("SELECT 1").await.unwrap();
+            assert_eq!(one, 1);
+
+            assert!(matches!(
+                fetch_exactly_one::<(i64,)>("SELECT 1 FROM DUAL WHERE 1 = 0").await,
+                Err(DbError::NotFound)
+            ));
+            assert!(matches!(
+                fetch_exactly_one::<(i64,)>("SELECT 1 UNION ALL SELECT 2 UNION ALL SELECT 3").await,
+                Err(DbError::TooManyRows { count: 2 })
+            ));
+        });
+    }
+
+    #[test]
+    fn test_find_streaming_against_database() {
+        test_util::run_with_db(|pool| async move {
+            sqlx::query("DROP TABLE IF EXISTS zirv_test_find_streaming")
+                .execute(pool)
+                .await
+                .unwrap();
+            sqlx::query(
+                "CREATE TABLE zirv_test_find_streaming (id INT PRIMARY KEY, tag VARCHAR(8))",
+            )
+            .execute(pool)
+            .await
+            .unwrap();
+            for id in 1..=50 {
+                let tag = if id % 20 == 0 { "match" } else { "other" };
+                sqlx::query("INSERT INTO zirv_test_find_streaming VALUES (?, ?)")
+                    .bind(id)
+                    .bind(tag)
+                    .execute(pool)
+                    .await
+                    .unwrap();
+            }
+
+            let mut read = 0;
+            let found: Option<(i32, String)> = find_streaming(
+                "SELECT id, tag FROM zirv_test_find_streaming ORDER BY id",
+                |(_, tag): &(i32, String)| {
+                    read += 1;
+                    tag == "match"
+                },
+            )
+            .await
+            .unwrap();
+
+            assert_eq!(found, Some((20, "match".to_owned())));
+            assert_eq!(read, 20);
+
+            sqlx::query("DROP TABLE zirv_test_find_streaming")
+                .execute(pool)
+                .await
+                .unwrap();
+        });
+    }
+}
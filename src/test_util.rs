@@ -0,0 +1,170 @@
+//! Helpers shared by the unit tests.
+//!
+//! Tests that need a real database are skipped unless the `DATABASE_URL` environment variable is
+//! set. They all run on one shared runtime, so the global pool (which is initialized once per
+//! process) is never used from a runtime other than the one that created its connections.
+
+use std::future::Future;
+use std::io;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use serde_json::json;
+use sqlx::{MySql, Pool};
+use tokio::runtime::Runtime;
+use tokio::sync::OnceCell;
+use tracing::Level;
+use zirv_config::register_config;
+
+static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+static POOL_READY: OnceCell<()> = OnceCell::const_new();
+
+/// Returns the database URL the DB-backed tests run against, if any.
+pub(crate) fn database_url() -> Option<String> {
+    std::env::var("DATABASE_URL").ok()
+}
+
+/// Runs `f` against the global pool, initializing it from `DATABASE_URL` on first use.
+///
+/// Does nothing (apart from logging) when no database is configured.
+pub(crate) fn run_with_db<F, Fut>(f: F)
+where
+    F: FnOnce(&'static Pool<MySql>) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let Some(url) = database_url() else {
+        eprintln!("DATABASE_URL not set. Skipping database test.");
+        return;
+    };
+
+    let runtime = RUNTIME.get_or_init(|| Runtime::new().expect("Failed to build test runtime"));
+    runtime.block_on(async move {
+        POOL_READY
+            .get_or_init(|| async {
+                register_config!("database", json!({ "url": url }));
+                crate::db::init_db_pool().await;
+            })
+            .await;
+        f(crate::db::get_db_pool()).await;
+    });
+}
+
+/// A database error with a given MySQL error number, for testing error classification.
+///
+/// It reports the number as its `code()`, which is how the crate's classification treats
+/// errors that are not a `MySqlDatabaseError`.
+#[derive(Debug)]
+pub(crate) struct MockDbError {
+    number: u16,
+    message: String,
+}
+
+impl std::fmt::Display for MockDbError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.number, self.message)
+    }
+}
+
+impl std::error::Error for MockDbError {}
+
+impl sqlx::error::DatabaseError for MockDbError {
+    fn message(&self) -> &str {
+        &self.message
+    }
+
+    fn code(&self) -> Option<std::borrow::Cow<'_, str>> {
+        Some(self.number.to_string().into())
+    }
+
+    fn as_error(&self) -> &(dyn std::error::Error + Send + Sync + 'static) {
+        self
+    }
+
+    fn as_error_mut(&mut self) -> &mut (dyn std::error::Error + Send + Sync + 'static) {
+        self
+    }
+
+    fn into_error(self: Box<Self>) -> Box<dyn std::error::Error + Send + Sync + 'static> {
+        self
+    }
+
+    fn kind(&self) -> sqlx::error::ErrorKind {
+        sqlx::error::ErrorKind::Other
+    }
+}
+
+/// Builds a `sqlx::Error::Database` with the given MySQL error number and message.
+pub(crate) fn db_error(number: u16, message: &str) -> sqlx::Error {
+    sqlx::Error::Database(Box::new(MockDbError {
+        number,
+        message: message.to_owned(),
+    }))
+}
+
+/// Creates a pool for `database` on localhost that does not connect until it is used.
+///
+/// Must be called from within a Tokio runtime.
+pub(crate) fn lazy_pool(database: &str) -> Pool<MySql> {
+    sqlx::mysql::MySqlPoolOptions::new()
+        .connect_lazy(&format!("mysql://localhost/{}", database))
+        .unwrap()
+}
+
+/// Returns the database `pool` connects to, to tell pools apart in tests.
+pub(crate) fn database_of(pool: &Pool<MySql>) -> String {
+    pool.connect_options()
+        .get_database()
+        .unwrap_or_default()
+        .to_owned()
+}
+
+/// Log lines captured by [`start_log_capture`].
+#[derive(Clone, Default)]
+pub(crate) struct LogBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl LogBuffer {
+    /// Returns the captured lines, one per event, as `LEVEL message`.
+    pub(crate) fn contents(&self) -> String {
+        let bytes = self.0.lock().unwrap().clone();
+        // The level is padded to five characters; drop the padding.
+        String::from_utf8(bytes)
+            .unwrap()
+            .lines()
+            .map(|line| format!("{}\n", line.trim_start()))
+            .collect()
+    }
+}
+
+impl io::Write for LogBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Captures everything logged on the current thread until the returned guard is dropped.
+///
+/// Tasks spawned on a current-thread runtime (as used by `#[tokio::test]`) are captured too.
+pub(crate) fn start_log_capture() -> (LogBuffer, tracing::subscriber::DefaultGuard) {
+    let buffer = LogBuffer::default();
+    let writer = buffer.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_max_level(Level::TRACE)
+        .with_writer(move || writer.clone())
+        .with_ansi(false)
+        .without_time()
+        .with_target(false)
+        .finish();
+    (buffer, tracing::subscriber::set_default(subscriber))
+}
+
+/// Collects everything logged while `f` runs, one line per event, as `LEVEL message`.
+pub(crate) fn capture_logs(f: impl FnOnce()) -> String {
+    let (buffer, guard) = start_log_capture();
+    f();
+    drop(guard);
+    buffer.contents()
+}
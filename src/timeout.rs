@@ -0,0 +1,193 @@
+use std::future::Future;
+use std::time::Duration;
+
+use sqlx::MySqlConnection;
+
+use crate::db::acquire;
+use crate::error::DbError;
+use crate::idle_stack::Acquired;
+use crate::logging::log_warn;
+use crate::retry::error_number;
+
+/// MySQL error number for "Query execution was interrupted, maximum statement execution time
+/// exceeded".
+const ER_QUERY_TIMEOUT: u16 = 3024;
+
+/// Runs `f` on a connection from the global pool, failing if it takes longer than `limit`.
+///
+/// Waiting for the connection is bounded by `database.acquire_timeout_secs` and not counted in
+/// `limit`, so the two kinds of timeout produce different errors: [`DbError::AcquireTimeout`]
+/// means no query was sent and the call can be retried, while [`DbError::QueryTimeout`] means the
+/// query was sent and may have had effects. A query aborted by the server because of
+/// `max_execution_time` is reported as a query timeout as well.
+///
+/// A query that times out client-side keeps running on the server until it notices the closed
+/// connection: the connection is discarded instead of being returned to the pool.
+///
+/// Every timeout is logged as a slow-query warning, with the current correlation id (see
+/// [`with_correlation_id`](crate::logging::with_correlation_id)) if there is one.
+///
+/// # Example
+/// ```rust,no_run
+/// use std::time::Duration;
+/// use zirv_db_sqlx::{error::DbError, timeout::with_query_timeout};
+///
+/// # async fn example() -> Result<(), DbError> {
+/// let total: i64 = with_query_timeout(Duration::from_secs(2), async |conn| {
+///     sqlx::query_scalar("SELECT SUM(amount) FROM orders")
+///         .fetch_one(conn)
+///         .await
+/// })
+/// .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn with_query_timeout<F, T>(limit: Duration, f: F) -> Result<T, DbError>
+where
+    F: AsyncFnOnce(&mut MySqlConnection) -> Result<T, sqlx::Error>,
+{
+    with_query_timeout_on(acquire(), limit, f).await
+}
+
+async fn with_query_timeout_on<F, T>(
+    acquire: impl Future<Output = Result<Acquired, sqlx::Error>>,
+    limit: Duration,
+    f: F,
+) -> Result<T, DbError>
+where
+    F: AsyncFnOnce(&mut MySqlConnection) -> Result<T, sqlx::Error>,
+{
+    let mut conn = acquire.await?;
+    let result = query_timeout(limit, f(&mut conn)).await;
+    if matches!(result, Err(DbError::QueryTimeout)) {
+        // The connection may still be receiving the abandoned result set.
+        drop(conn.detach());
+    }
+    result
+}
+
+/// Awaits `query` for at most `limit`, classifying and logging timeouts as
+/// [`DbError::QueryTimeout`].
+async fn query_timeout<T>(
+    limit: Duration,
+    query: impl Future<Output = Result<T, sqlx::Error>>,
+) -> Result<T, DbError> {
+    match tokio::time::timeout(limit, query).await {
+        Err(_) => {
+            log_warn(format_args!("Slow query cancelled after {:?}", limit));
+            Err(DbError::QueryTimeout)
+        }
+        Ok(Err(sqlx::Error::Database(db)))
+            if error_number(db.as_ref()) == Some(ER_QUERY_TIMEOUT) =>
+        {
+            log_warn(format_args!(
+                "Slow query aborted by the server (max_execution_time)"
+            ));
+            Err(DbError::QueryTimeout)
+        }
+        Ok(result) => result.map_err(DbError::from),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::idle_stack::acquire_from;
+    use crate::logging::with_correlation_id;
+    use crate::test_util;
+    use sqlx::mysql::MySqlPoolOptions;
+
+    const LIMIT: Duration = Duration::from_millis(20);
+
+    #[tokio::test]
+    async fn test_slow_query_is_a_query_timeout() {
+        let result = query_timeout(LIMIT, async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok(())
+        })
+        .await;
+        assert!(matches!(result, Err(DbError::QueryTimeout)));
+    }
+
+    #[tokio::test]
+    async fn test_slow_query_warning_includes_correlation_id() {
+        let (logs, guard) = test_util::start_log_capture();
+        let result = with_correlation_id("req-7", async {
+            query_timeout(LIMIT, async {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                Ok(())
+            })
+            .await
+        })
+        .await;
+        drop(guard);
+
+        assert!(matches!(result, Err(DbError::QueryTimeout)));
+        assert_eq!(
+            logs.contents(),
+            "WARN [correlation_id=req-7] Slow query cancelled after 20ms\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_server_side_timeout_is_a_query_timeout() {
+        let result: Result<(), _> = query_timeout(LIMIT, async {
+            Err(test_util::db_error(
+                3024,
+                "Query execution was interrupted, maximum statement execution time exceeded",
+            ))
+        })
+        .await;
+        assert!(matches!(result, Err(DbError::QueryTimeout)));
+    }
+
+    #[tokio::test]
+    async fn test_other_errors_are_kept() {
+        let result: Result<(), _> =
+            query_timeout(LIMIT, async { Err(sqlx::Error::RowNotFound) }).await;
+        assert!(matches!(
+            result,
+            Err(DbError::Sqlx(sqlx::Error::RowNotFound))
+        ));
+        assert_eq!(query_timeout(LIMIT, async { Ok(7) }).await.unwrap(), 7);
+    }
+
+    #[test]
+    fn test_pool_timeout_is_an_acquire_timeout() {
+        assert!(matches!(
+            DbError::from(sqlx::Error::PoolTimedOut),
+            DbError::AcquireTimeout
+        ));
+    }
+
+    #[test]
+    fn test_acquire_and_query_timeouts_against_database() {
+        test_util::run_with_db(|_| async {
+            let url = test_util::database_url().unwrap();
+            let pool = MySqlPoolOptions::new()
+                .max_connections(1)
+                .acquire_timeout(Duration::from_millis(200))
+                .connect(&url)
+                .await
+                .unwrap();
+
+            let result = with_query_timeout_on(
+                acquire_from(&pool, None),
+                Duration::from_millis(100),
+                async |conn| sqlx::query("SELECT SLEEP(2)").execute(conn).await,
+            )
+            .await;
+            assert!(matches!(result, Err(DbError::QueryTimeout)));
+
+            // With the only connection held, acquiring another one times out.
+            let _held = pool.acquire().await.unwrap();
+            let result = with_query_timeout_on(
+                acquire_from(&pool, None),
+                Duration::from_secs(5),
+                async |conn| sqlx::query("SELECT 1").execute(conn).await,
+            )
+            .await;
+            assert!(matches!(result, Err(DbError::AcquireTimeout)));
+        });
+    }
+}
@@ -0,0 +1,1015 @@
+use std::ops::{Deref, DerefMut};
+use std::time::Duration;
+
+use serde_json::Value;
+use sqlx::{FromRow, MySql, MySqlConnection, Transaction, mysql::MySqlRow, pool::PoolConnection};
+
+use crate::{
+    db::{get_db_pool, get_read_pool},
+    error::DbError,
+    redact::format_db_error,
+    retry::{backoff_delay, is_deadlock},
+    sql::json_arguments,
+    watchdog::{WatchdogConfig, watch_transaction},
+};
+
+type Hook = Box<dyn FnOnce() + Send + 'static>;
+
+/// Callbacks waiting for the outcome of a transaction.
+///
+/// Whatever happens, each set of hooks runs at most once: completing the transaction runs the
+/// matching hooks and discards the others, and dropping pending hooks counts as a rollback.
+#[derive(Default)]
+struct TxHooks {
+    after_commit: Vec<Hook>,
+    after_rollback: Vec<Hook>,
+}
+
+impl TxHooks {
+    fn complete(&mut self, committed: bool) {
+        let after_commit = std::mem::take(&mut self.after_commit);
+        let after_rollback = std::mem::take(&mut self.after_rollback);
+        let hooks = if committed {
+            after_commit
+        } else {
+            after_rollback
+        };
+        for hook in hooks {
+            hook();
+        }
+    }
+}
+
+impl Drop for TxHooks {
+    fn drop(&mut self) {
+        self.complete(false);
+    }
+}
+
+/// A transaction on the global pool that runs callbacks once its outcome is known.
+///
+/// Dereferences to the underlying connection, so it can be used as an executor with
+/// `&mut *tx`, just like a plain sqlx transaction.
+///
+/// - `after_commit` callbacks run after a successful commit. They never run if the commit fails.
+/// - `after_rollback` callbacks run after an explicit rollback, after a failed commit (the server
+///   discards the transaction) and when the guard is dropped without being committed (sqlx then
+///   rolls the transaction back).
+///
+/// # Example
+/// ```rust,no_run
+/// use zirv_db_sqlx::transaction::TxGuard;
+///
+/// async fn create_user() -> Result<(), sqlx::Error> {
+///     let mut tx = TxGuard::begin().await?;
+///     sqlx::query("INSERT INTO users (name) VALUES (?)")
+///         .bind("Jane Doe")
+///         .execute(&mut *tx)
+///         .await?;
+///     tx.after_commit(|| println!("publish UserCreated"));
+///     tx.commit().await
+/// }
+/// ```
+pub struct TxGuard {
+    tx: Option<Transaction<'static, MySql>>,
+    hooks: TxHooks,
+}
+
+impl TxGuard {
+    /// Starts a new transaction on the global pool.
+    pub async fn begin() -> Result<Self, sqlx::Error> {
+        Ok(TxGuard::new(get_db_pool().begin().await?))
+    }
+
+    /// Wraps an already started transaction.
+    pub fn new(tx: Transaction<'static, MySql>) -> Self {
+        TxGuard {
+            tx: Some(tx),
+            hooks: TxHooks::default(),
+        }
+    }
+
+    /// Registers a callback to run once the transaction has been committed.
+    pub fn after_commit(&mut self, hook: impl FnOnce() + Send + 'static) {
+        self.hooks.after_commit.push(Box::new(hook));
+    }
+
+    /// Registers a callback to run once the transaction has been rolled back.
+    pub fn after_rollback(&mut self, hook: impl FnOnce() + Send + 'static) {
+        self.hooks.after_rollback.push(Box::new(hook));
+    }
+
+    /// Commits the transaction, then runs the `after_commit` callbacks.
+    ///
+    /// If the commit fails, the `after_rollback` callbacks run instead and the error is returned.
+    pub async fn commit(mut self) -> Result<(), sqlx::Error> {
+        let tx = self.tx.take().expect("transaction already completed");
+        let result = tx.commit().await;
+        self.hooks.complete(result.is_ok());
+        result
+    }
+
+    /// Rolls the transaction back, then runs the `after_rollback` callbacks.
+    pub async fn rollback(mut self) -> Result<(), sqlx::Error> {
+        let tx = self.tx.take().expect("transaction already completed");
+        let result = tx.rollback().await;
+        self.hooks.complete(false);
+        result
+    }
+}
+
+impl Deref for TxGuard {
+    type Target = MySqlConnection;
+
+    fn deref(&self) -> &Self::Target {
+        self.tx.as_ref().expect("transaction already completed")
+    }
+}
+
+impl DerefMut for TxGuard {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.tx.as_mut().expect("transaction already completed")
+    }
+}
+
+/// Runs `f` inside a transaction on the global pool.
+///
+/// The transaction is committed if `f` returns `Ok` and rolled back if it returns `Err`. Hooks
+/// registered on the [`TxGuard`] passed to `f` run once the outcome is known. If the rollback
+/// itself fails, that failure is logged and the error returned by `f` is kept.
+///
+/// # Watchdog
+/// If `database.max_transaction_duration_secs` is set, a transaction still running after that
+/// many seconds is logged as an error, to catch transactions that hold locks for too long. With
+/// `database.kill_long_transactions` set to `true`, the watchdog additionally runs
+/// `KILL QUERY` for the transaction's connection: the statement running at that moment fails
+/// (a statement such as `SLEEP` merely returns early), which usually makes `f` return an error
+/// and the transaction roll back. This is opt-in because the killed statement is not
+/// necessarily the one at fault, and an idle transaction between statements is not affected.
+///
+/// # Example
+/// ```rust,no_run
+/// use zirv_db_sqlx::transaction::with_transaction;
+///
+/// async fn create_user() -> Result<u64, sqlx::Error> {
+///     with_transaction(async |tx| {
+///         let result = sqlx::query("INSERT INTO users (name) VALUES (?)")
+///             .bind("Jane Doe")
+///             .execute(&mut **tx)
+///             .await?;
+///         tx.after_commit(|| println!("publish UserCreated"));
+///         Ok(result.last_insert_id())
+///     })
+///     .await
+/// }
+/// ```
+pub async fn with_transaction<F, T, E>(f: F) -> Result<T, E>
+where
+    F: AsyncFnOnce(&mut TxGuard) -> Result<T, E>,
+    E: From<sqlx::Error>,
+{
+    run_transaction(WatchdogConfig::global(), f).await
+}
+
+async fn run_transaction<F, T, E>(watchdog: WatchdogConfig, f: F) -> Result<T, E>
+where
+    F: AsyncFnOnce(&mut TxGuard) -> Result<T, E>,
+    E: From<sqlx::Error>,
+{
+    let mut tx = TxGuard::begin().await?;
+    // Watches the transaction until it is completed, i.e. until this function returns.
+    let _watchdog = watch_transaction(&mut tx, watchdog).await?;
+    match f(&mut tx).await {
+        Ok(value) => {
+            tx.commit().await?;
+            Ok(value)
+        }
+        Err(e) => {
+            if let Err(rollback_err) = tx.rollback().await {
+                crate::logging::log_error(format_args!(
+                    "Failed to rollback transaction: {}",
+                    format_db_error(&rollback_err)
+                ));
+            }
+            Err(e)
+        }
+    }
+}
+
+/// Runs a list of statements in a single transaction.
+///
+/// Each statement is given as its SQL and its bind values (bound as described in
+/// [`json_arguments`]). The statements run in order and the transaction is committed once all
+/// of them succeeded; if one fails, nothing is applied.
+///
+/// # Returns
+/// The number of rows affected by each statement, in order.
+///
+/// # Errors
+/// Returns [`DbError::StatementFailed`] with the index of the failing statement after rolling
+/// back, or [`DbError::Sqlx`] if the transaction cannot be started or committed.
+///
+/// # Example
+/// ```rust,no_run
+/// use serde_json::json;
+/// use zirv_db_sqlx::transaction::execute_all_atomic;
+///
+/// # async fn example() -> Result<(), zirv_db_sqlx::error::DbError> {
+/// let affected = execute_all_atomic(&[
+///     ("UPDATE accounts SET balance = balance - ? WHERE id = ?".to_owned(), vec![json!(10), json!(1)]),
+///     ("UPDATE accounts SET balance = balance + ? WHERE id = ?".to_owned(), vec![json!(10), json!(2)]),
+/// ])
+/// .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn execute_all_atomic(stmts: &[(String, Vec<Value>)]) -> Result<Vec<u64>, DbError> {
+    with_transaction(async |tx| {
+        let mut affected = Vec::with_capacity(stmts.len());
+        for (index, (sql, binds)) in stmts.iter().enumerate() {
+            let failed = |e| match e {
+                DbError::Sqlx(source) => DbError::StatementFailed { index, source },
+                other => other,
+            };
+            let args = json_arguments(binds).map_err(failed)?;
+            crate::statement_cache::track(sql);
+            #[cfg(feature = "query-recorder")]
+            let started = std::time::Instant::now();
+            let result = sqlx::query_with(sql, args)
+                .execute(&mut **tx)
+                .await
+                .map_err(|source| DbError::StatementFailed { index, source })?;
+            #[cfg(feature = "query-recorder")]
+            crate::recorder::record_values(sql, binds, started, result.rows_affected());
+            affected.push(result.rows_affected());
+        }
+        Ok(affected)
+    })
+    .await
+}
+
+/// Runs a `SELECT` within a transaction and locks the returned rows until it completes.
+///
+/// `FOR UPDATE` is appended to `sql` (after stripping a trailing `;`), so `sql` must not already
+/// end in a locking clause. With `skip_locked`, `SKIP LOCKED` is appended as well: rows locked by
+/// other transactions are left out of the result instead of waiting for them, which lets
+/// concurrent workers claim disjoint rows from a queue table. `SKIP LOCKED` requires MySQL 8.0 or
+/// MariaDB 10.6.
+///
+/// `tx` is the connection of an open transaction (a [`TxGuard`] or a sqlx `Transaction`). Under
+/// autocommit the locks would be released as soon as the statement finished.
+///
+/// # Example
+/// ```rust,no_run
+/// use zirv_db_sqlx::transaction::{fetch_for_update, with_transaction};
+///
+/// #[derive(sqlx::FromRow)]
+/// struct Job {
+///     id: i64,
+///     payload: String,
+/// }
+///
+/// async fn claim_jobs() -> Result<Vec<Job>, sqlx::Error> {
+///     with_transaction(async |tx| {
+///         let jobs: Vec<Job> = fetch_for_update(
+///             &mut *tx,
+///             "SELECT id, payload FROM jobs WHERE state = 'pending' ORDER BY id LIMIT 10",
+///             true,
+///         )
+///         .await?;
+///         for job in &jobs {
+///             sqlx::query("UPDATE jobs SET state = 'running' WHERE id = ?")
+///                 .bind(job.id)
+///                 .execute(&mut **tx)
+///                 .await?;
+///         }
+///         Ok(jobs)
+///     })
+///     .await
+/// }
+/// ```
+pub async fn fetch_for_update<T>(
+    tx: &mut MySqlConnection,
+    sql: &str,
+    skip_locked: bool,
+) -> Result<Vec<T>, sqlx::Error>
+where
+    T: for<'r> FromRow<'r, MySqlRow> + Send + Unpin,
+{
+    let sql = for_update_sql(sql, skip_locked);
+    crate::statement_cache::track(&sql);
+    #[cfg(feature = "query-recorder")]
+    let started = std::time::Instant::now();
+    let rows = sqlx::query_as::<_, T>(&sql).fetch_all(tx).await?;
+    #[cfg(feature = "query-recorder")]
+    crate::recorder::record_values(&sql, &[], started, rows.len() as u64);
+    Ok(rows)
+}
+
+/// Appends the locking clause used by [`fetch_for_update`] to `sql`.
+fn for_update_sql(sql: &str, skip_locked: bool) -> String {
+    let sql = sql.trim_end().trim_end_matches(';').trim_end();
+    if skip_locked {
+        format!("{} FOR UPDATE SKIP LOCKED", sql)
+    } else {
+        format!("{} FOR UPDATE", sql)
+    }
+}
+
+/// A transaction that has been open for a while, as listed by [`long_running_transactions`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LongTransaction {
+    /// The id of the connection running the transaction, as used by `KILL`.
+    pub connection_id: u64,
+    /// How long the transaction has been open, in whole seconds.
+    pub age: Duration,
+    /// The InnoDB state, e.g. `RUNNING` or `LOCK WAIT`.
+    pub state: String,
+    /// The statement currently running in the transaction, or `None` if it is idle.
+    pub query: Option<String>,
+}
+
+/// Lists the transactions, other than the caller's own, that have been open for at least
+/// `min_age`, oldest first.
+///
+/// The list comes from `information_schema.innodb_trx`, so it covers InnoDB transactions that
+/// have read or written data; reading it requires the `PROCESS` privilege. An idle transaction
+/// (`query` is `None`) that has been open for long usually belongs to code that forgot to commit,
+/// and holds its locks, including metadata locks that block DDL, until it does.
+///
+/// # Example
+/// ```rust,no_run
+/// use std::time::Duration;
+/// use zirv_db_sqlx::transaction::long_running_transactions;
+///
+/// # async fn example() -> Result<(), sqlx::Error> {
+/// for trx in long_running_transactions(Duration::from_secs(60)).await? {
+///     eprintln!("connection {} open for {:?}", trx.connection_id, trx.age);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub async fn long_running_transactions(
+    min_age: Duration,
+) -> Result<Vec<LongTransaction>, sqlx::Error> {
+    let rows: Vec<(u64, i64, String, Option<String>)> = sqlx::query_as(
+        "SELECT trx_mysql_thread_id, TIMESTAMPDIFF(SECOND, trx_started, NOW()), \
+                CAST(trx_state AS CHAR), CAST(trx_query AS CHAR) \
+         FROM information_schema.innodb_trx \
+         WHERE trx_mysql_thread_id <> CONNECTION_ID() \
+           AND trx_started <= NOW() - INTERVAL ? SECOND \
+         ORDER BY trx_started",
+    )
+    .bind(min_age.as_secs())
+    .fetch_all(get_db_pool())
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(connection_id, age, state, query)| LongTransaction {
+            connection_id,
+            age: Duration::from_secs(age.max(0) as u64),
+            state,
+            query,
+        })
+        .collect())
+}
+
+/// Runs `f` in a fresh transaction, retrying the whole block when it hits a deadlock.
+///
+/// Each attempt begins a new transaction on the global pool, runs `f` and commits. If `f` or the
+/// commit fails with a deadlock (see [`is_deadlock`]), the transaction is rolled back and the
+/// entire block is run again in a new transaction, with exponential backoff, up to
+/// `max_retries` times. Any other error rolls back and is returned immediately.
+///
+/// Because the block may run several times, it must not have side effects outside of the
+/// transaction; use [`TxGuard::after_commit`] for those.
+///
+/// See also the [`transactional_retry!`](crate::transactional_retry) macro.
+pub async fn transactional_retry<F, T>(max_retries: u32, mut f: F) -> Result<T, sqlx::Error>
+where
+    F: AsyncFnMut(&mut TxGuard) -> Result<T, sqlx::Error>,
+{
+    retry_transaction(&mut GlobalTxSource, max_retries, &mut f).await
+}
+
+/// A read snapshot on the read pool paired with a write transaction on the primary.
+///
+/// For workflows that read from a replica and then write to the primary based on what they
+/// read. [`ReadWriteScope::read`] is a `REPEATABLE READ`, read-only transaction on
+/// [`get_read_pool`], started `WITH CONSISTENT SNAPSHOT`: every read sees the snapshot taken when
+/// the scope began, however long the scope stays open, whatever isolation level the server or
+/// session defaults to. [`ReadWriteScope::write`] is an ordinary [`TxGuard`] on the primary.
+///
+/// # Atomicity
+/// These are two separate transactions, possibly on two servers, and nothing makes them atomic
+/// together:
+/// - the snapshot may lag behind the primary (replication delay), and rows it shows may have
+///   changed on the primary by the time the write runs. Re-check or lock (e.g. with
+///   [`fetch_for_update`]) anything on the primary that the write must not contradict;
+/// - the write transaction does not see the snapshot, and the snapshot never sees the write,
+///   even when both pools connect to the same server;
+/// - only the write side has effects to undo. [`ReadWriteScope::rollback`] and
+///   [`with_read_write_scope`] roll it back together with ending the snapshot.
+///
+/// Dropping the scope without committing rolls the write transaction back and closes the
+/// connection of the read snapshot.
+///
+/// # Example
+/// ```rust,no_run
+/// use zirv_db_sqlx::transaction::ReadWriteScope;
+///
+/// async fn restock(product_id: i64) -> Result<(), sqlx::Error> {
+///     let mut scope = ReadWriteScope::begin().await?;
+///     let sold: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM orders WHERE product_id = ?")
+///         .bind(product_id)
+///         .fetch_one(scope.read())
+///         .await?;
+///     sqlx::query("UPDATE products SET stock = stock + ? WHERE id = ?")
+///         .bind(sold)
+///         .bind(product_id)
+///         .execute(&mut **scope.write())
+///         .await?;
+///     scope.commit().await
+/// }
+/// ```
+pub struct ReadWriteScope {
+    read: ReadSnapshot,
+    write: TxGuard,
+}
+
+impl ReadWriteScope {
+    /// Starts the read snapshot on the read pool and the write transaction on the primary.
+    pub async fn begin() -> Result<Self, sqlx::Error> {
+        let read = ReadSnapshot::begin().await?;
+        let write = TxGuard::begin().await?;
+        Ok(ReadWriteScope { read, write })
+    }
+
+    /// Returns the connection of the read snapshot.
+    pub fn read(&mut self) -> &mut MySqlConnection {
+        self.read
+            .conn
+            .as_deref_mut()
+            .expect("read snapshot already ended")
+    }
+
+    /// Returns the write transaction on the primary.
+    pub fn write(&mut self) -> &mut TxGuard {
+        &mut self.write
+    }
+
+    /// Ends the read snapshot and commits the write transaction.
+    ///
+    /// Ending the snapshot cannot affect the outcome of the write, so a failure to do so is
+    /// only logged.
+    pub async fn commit(self) -> Result<(), sqlx::Error> {
+        end_snapshot(self.read).await;
+        self.write.commit().await
+    }
+
+    /// Ends the read snapshot and rolls the write transaction back.
+    pub async fn rollback(self) -> Result<(), sqlx::Error> {
+        end_snapshot(self.read).await;
+        self.write.rollback().await
+    }
+}
+
+/// The read-only transaction of a [`ReadWriteScope`].
+///
+/// sqlx's [`Transaction`] can only be started with a plain `BEGIN`, so the transaction is
+/// managed here. A connection whose transaction may still be open, because starting or ending
+/// it failed or the snapshot was dropped without being ended, is closed instead of going back
+/// to the pool.
+struct ReadSnapshot {
+    conn: Option<PoolConnection<MySql>>,
+}
+
+impl ReadSnapshot {
+    async fn begin() -> Result<Self, sqlx::Error> {
+        let mut conn = get_read_pool().acquire().await?;
+        // The isolation level applies to the next transaction only. If either statement fails,
+        // the connection is closed, so it cannot leak into another transaction.
+        for sql in [
+            "SET TRANSACTION ISOLATION LEVEL REPEATABLE READ",
+            "START TRANSACTION WITH CONSISTENT SNAPSHOT, READ ONLY",
+        ] {
+            if let Err(e) = sqlx::raw_sql(sql).execute(&mut *conn).await {
+                drop(conn.detach());
+                return Err(e);
+            }
+        }
+        Ok(ReadSnapshot { conn: Some(conn) })
+    }
+
+    async fn end(mut self) -> Result<(), sqlx::Error> {
+        let mut conn = self.conn.take().expect("read snapshot already ended");
+        if let Err(e) = sqlx::raw_sql("ROLLBACK").execute(&mut *conn).await {
+            drop(conn.detach());
+            return Err(e);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for ReadSnapshot {
+    fn drop(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            drop(conn.detach());
+        }
+    }
+}
+
+/// Ends a read-only transaction, logging a failure instead of returning it.
+async fn end_snapshot(read: ReadSnapshot) {
+    if let Err(e) = read.end().await {
+        crate::logging::log_warn(format_args!(
+            "Failed to end read snapshot: {}",
+            format_db_error(&e)
+        ));
+    }
+}
+
+/// Runs `f` in a [`ReadWriteScope`], committing the write side if `f` returns `Ok` and rolling
+/// it back if it returns `Err`.
+///
+/// As with [`with_transaction`], a failed rollback is logged and the error returned by `f` is
+/// kept. See [`ReadWriteScope`] for what this does and does not guarantee.
+pub async fn with_read_write_scope<F, T, E>(f: F) -> Result<T, E>
+where
+    F: AsyncFnOnce(&mut ReadWriteScope) -> Result<T, E>,
+    E: From<sqlx::Error>,
+{
+    let mut scope = ReadWriteScope::begin().await?;
+    match f(&mut scope).await {
+        Ok(value) => {
+            scope.commit().await?;
+            Ok(value)
+        }
+        Err(e) => {
+            if let Err(rollback_err) = scope.rollback().await {
+                crate::logging::log_error(format_args!(
+                    "Failed to rollback transaction: {}",
+                    format_db_error(&rollback_err)
+                ));
+            }
+            Err(e)
+        }
+    }
+}
+
+/// Begins and completes the transactions of [`transactional_retry`].
+trait TxSource {
+    type Tx;
+
+    async fn begin(&mut self) -> Result<Self::Tx, sqlx::Error>;
+    async fn commit(&mut self, tx: Self::Tx) -> Result<(), sqlx::Error>;
+    async fn rollback(&mut self, tx: Self::Tx) -> Result<(), sqlx::Error>;
+}
+
+/// Transactions on the global pool.
+struct GlobalTxSource;
+
+impl TxSource for GlobalTxSource {
+    type Tx = TxGuard;
+
+    async fn begin(&mut self) -> Result<TxGuard, sqlx::Error> {
+        TxGuard::begin().await
+    }
+
+    async fn commit(&mut self, tx: TxGuard) -> Result<(), sqlx::Error> {
+        tx.commit().await
+    }
+
+    async fn rollback(&mut self, tx: TxGuard) -> Result<(), sqlx::Error> {
+        tx.rollback().await
+    }
+}
+
+async fn retry_transaction<S, F, T>(
+    source: &mut S,
+    max_retries: u32,
+    f: &mut F,
+) -> Result<T, sqlx::Error>
+where
+    S: TxSource,
+    F: AsyncFnMut(&mut S::Tx) -> Result<T, sqlx::Error>,
+{
+    let mut attempt = 0;
+    loop {
+        let mut tx = source.begin().await?;
+        let result = match f(&mut tx).await {
+            Ok(value) => source.commit(tx).await.map(|()| value),
+            Err(e) => {
+                if let Err(rollback_err) = source.rollback(tx).await {
+                    crate::logging::log_error(format_args!(
+                        "Failed to rollback transaction: {}",
+                        format_db_error(&rollback_err)
+                    ));
+                }
+                Err(e)
+            }
+        };
+
+        match result {
+            Err(e) if attempt < max_retries && is_deadlock(&e) => {
+                attempt += 1;
+                tokio::time::sleep(backoff_delay(attempt)).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+    use serde_json::json;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::{Duration, Instant};
+
+    fn counting_hooks() -> (TxHooks, Arc<AtomicUsize>, Arc<AtomicUsize>) {
+        let commits = Arc::new(AtomicUsize::new(0));
+        let rollbacks = Arc::new(AtomicUsize::new(0));
+        let mut hooks = TxHooks::default();
+        let c = commits.clone();
+        hooks.after_commit.push(Box::new(move || {
+            c.fetch_add(1, Ordering::SeqCst);
+        }));
+        let r = rollbacks.clone();
+        hooks.after_rollback.push(Box::new(move || {
+            r.fetch_add(1, Ordering::SeqCst);
+        }));
+        (hooks, commits, rollbacks)
+    }
+
+    #[test]
+    fn test_after_commit_runs_only_on_commit() {
+        let (mut hooks, commits, rollbacks) = counting_hooks();
+        hooks.complete(true);
+        drop(hooks);
+        assert_eq!(commits.load(Ordering::SeqCst), 1);
+        assert_eq!(rollbacks.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_after_rollback_runs_only_on_rollback() {
+        let (mut hooks, commits, rollbacks) = counting_hooks();
+        hooks.complete(false);
+        drop(hooks);
+        assert_eq!(commits.load(Ordering::SeqCst), 0);
+        assert_eq!(rollbacks.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_dropped_hooks_count_as_rollback() {
+        let (hooks, commits, rollbacks) = counting_hooks();
+        drop(hooks);
+        assert_eq!(commits.load(Ordering::SeqCst), 0);
+        assert_eq!(rollbacks.load(Ordering::SeqCst), 1);
+    }
+
+    #[derive(Default)]
+    struct MockTxSource {
+        begins: u32,
+        commits: u32,
+        rollbacks: u32,
+        fail_rollback: bool,
+    }
+
+    impl TxSource for MockTxSource {
+        type Tx = u32;
+
+        async fn begin(&mut self) -> Result<u32, sqlx::Error> {
+            self.begins += 1;
+            Ok(self.begins)
+        }
+
+        async fn commit(&mut self, _tx: u32) -> Result<(), sqlx::Error> {
+            self.commits += 1;
+            Ok(())
+        }
+
+        async fn rollback(&mut self, _tx: u32) -> Result<(), sqlx::Error> {
+            self.rollbacks += 1;
+            if self.fail_rollback {
+                return Err(sqlx::Error::PoolClosed);
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_deadlocked_block_is_retried_in_a_new_transaction() {
+        let mut source = MockTxSource::default();
+        let result = retry_transaction(&mut source, 3, &mut async |tx: &mut u32| {
+            if *tx == 1 {
+                Err(test_util::db_error(
+                    1213,
+                    "Deadlock found when trying to get lock",
+                ))
+            } else {
+                Ok(*tx)
+            }
+        })
+        .await;
+
+        assert_eq!(result.unwrap(), 2);
+        assert_eq!(source.begins, 2);
+        assert_eq!(source.commits, 1);
+        assert_eq!(source.rollbacks, 1);
+    }
+
+    #[tokio::test]
+    async fn test_other_errors_are_not_retried() {
+        let mut source = MockTxSource::default();
+        let result: Result<(), _> = retry_transaction(&mut source, 3, &mut async |_: &mut u32| {
+            Err(sqlx::Error::RowNotFound)
+        })
+        .await;
+
+        assert!(matches!(result, Err(sqlx::Error::RowNotFound)));
+        assert_eq!((source.begins, source.commits, source.rollbacks), (1, 0, 1));
+    }
+
+    #[tokio::test]
+    async fn test_deadlock_retries_are_bounded() {
+        let mut source = MockTxSource::default();
+        let result: Result<(), _> = retry_transaction(&mut source, 2, &mut async |_: &mut u32| {
+            Err(test_util::db_error(
+                1213,
+                "Deadlock found when trying to get lock",
+            ))
+        })
+        .await;
+
+        assert!(is_deadlock(&result.unwrap_err()));
+        assert_eq!((source.begins, source.commits, source.rollbacks), (3, 0, 3));
+    }
+
+    #[tokio::test]
+    async fn test_failed_rollback_is_logged_with_correlation_id() {
+        let mut source = MockTxSource {
+            fail_rollback: true,
+            ..MockTxSource::default()
+        };
+        let (logs, guard) = test_util::start_log_capture();
+        let result: Result<(), _> = crate::logging::with_correlation_id(
+            "req-42",
+            retry_transaction(&mut source, 0, &mut async |_: &mut u32| {
+                Err(sqlx::Error::RowNotFound)
+            }),
+        )
+        .await;
+        drop(guard);
+
+        assert!(matches!(result, Err(sqlx::Error::RowNotFound)));
+        assert_eq!(
+            logs.contents(),
+            "ERROR [correlation_id=req-42] Failed to rollback transaction: PoolClosed\n"
+        );
+    }
+
+    #[test]
+    fn test_with_transaction_runs_hooks_for_outcome() {
+        test_util::run_with_db(|_| async {
+            let commits = Arc::new(AtomicUsize::new(0));
+            let rollbacks = Arc::new(AtomicUsize::new(0));
+
+            let (c, r) = (commits.clone(), rollbacks.clone());
+            with_transaction(async move |tx| {
+                sqlx::query("SELECT 1").execute(&mut **tx).await?;
+                tx.after_commit(move || {
+                    c.fetch_add(1, Ordering::SeqCst);
+                });
+                tx.after_rollback(move || {
+                    r.fetch_add(1, Ordering::SeqCst);
+                });
+                Ok::<_, sqlx::Error>(())
+            })
+            .await
+            .unwrap();
+            assert_eq!(commits.load(Ordering::SeqCst), 1);
+            assert_eq!(rollbacks.load(Ordering::SeqCst), 0);
+
+            let (c, r) = (commits.clone(), rollbacks.clone());
+            let result = with_transaction(async move |tx| {
+                tx.after_commit(move || {
+                    c.fetch_add(1, Ordering::SeqCst);
+                });
+                tx.after_rollback(move || {
+                    r.fetch_add(1, Ordering::SeqCst);
+                });
+                Err::<(), _>(sqlx::Error::RowNotFound)
+            })
+            .await;
+            assert!(matches!(result, Err(sqlx::Error::RowNotFound)));
+            assert_eq!(commits.load(Ordering::SeqCst), 1);
+            assert_eq!(rollbacks.load(Ordering::SeqCst), 1);
+        });
+    }
+
+    #[test]
+    fn test_read_write_scope_reads_a_snapshot_and_rolls_back_writes() {
+        test_util::run_with_db(|pool| async move {
+            sqlx::query("DROP TABLE IF EXISTS zirv_test_rw_scope")
+                .execute(pool)
+                .await
+                .unwrap();
+            sqlx::query("CREATE TABLE zirv_test_rw_scope (id INT PRIMARY KEY, total BIGINT)")
+                .execute(pool)
+                .await
+                .unwrap();
+            sqlx::query("INSERT INTO zirv_test_rw_scope VALUES (1, 10), (2, 20)")
+                .execute(pool)
+                .await
+                .unwrap();
+            let sum = "SELECT CAST(SUM(total) AS SIGNED) FROM zirv_test_rw_scope WHERE id < 100";
+
+            // Read, then write what was read; rows committed meanwhile stay out of the snapshot.
+            let written = with_read_write_scope(async |scope| {
+                let before: i64 = sqlx::query_scalar(sum).fetch_one(scope.read()).await?;
+                sqlx::query("INSERT INTO zirv_test_rw_scope VALUES (3, 30)")
+                    .execute(pool)
+                    .await?;
+                let after: i64 = sqlx::query_scalar(sum).fetch_one(scope.read()).await?;
+                assert_eq!(before, after);
+                sqlx::query("INSERT INTO zirv_test_rw_scope VALUES (100, ?)")
+                    .bind(before)
+                    .execute(&mut **scope.write())
+                    .await?;
+                Ok::<_, sqlx::Error>(before)
+            })
+            .await
+            .unwrap();
+            assert_eq!(written, 30);
+            let stored: i64 =
+                sqlx::query_scalar("SELECT total FROM zirv_test_rw_scope WHERE id = 100")
+                    .fetch_one(pool)
+                    .await
+                    .unwrap();
+            assert_eq!(stored, 30);
+
+            // The read side is read-only and REPEATABLE READ, whatever the session default.
+            let mut scope = ReadWriteScope::begin().await.unwrap();
+            let isolation: String =
+                sqlx::query_scalar("SELECT CAST(@@transaction_isolation AS CHAR)")
+                    .fetch_one(scope.read())
+                    .await
+                    .unwrap();
+            assert_eq!(isolation, "REPEATABLE-READ");
+            assert!(
+                sqlx::query("DELETE FROM zirv_test_rw_scope")
+                    .execute(scope.read())
+                    .await
+                    .is_err()
+            );
+            scope.rollback().await.unwrap();
+
+            // An error rolls the write side back.
+            let result = with_read_write_scope(async |scope| {
+                sqlx::query("INSERT INTO zirv_test_rw_scope VALUES (101, 0)")
+                    .execute(&mut **scope.write())
+                    .await?;
+                Err::<(), _>(sqlx::Error::RowNotFound)
+            })
+            .await;
+            assert!(matches!(result, Err(sqlx::Error::RowNotFound)));
+            let count: i64 =
+                sqlx::query_scalar("SELECT COUNT(*) FROM zirv_test_rw_scope WHERE id = 101")
+                    .fetch_one(pool)
+                    .await
+                    .unwrap();
+            assert_eq!(count, 0);
+
+            sqlx::query("DROP TABLE zirv_test_rw_scope")
+                .execute(pool)
+                .await
+                .unwrap();
+        });
+    }
+
+    #[test]
+    fn test_execute_all_atomic_rolls_back_on_failure() {
+        test_util::run_with_db(|pool| async move {
+            sqlx::query("DROP TABLE IF EXISTS zirv_test_atomic")
+                .execute(pool)
+                .await
+                .unwrap();
+            sqlx::query("CREATE TABLE zirv_test_atomic (id INT PRIMARY KEY, name VARCHAR(16))")
+                .execute(pool)
+                .await
+                .unwrap();
+            let insert = "INSERT INTO zirv_test_atomic (id, name) VALUES (?, ?)".to_owned();
+
+            let result = execute_all_atomic(&[
+                (insert.clone(), vec![json!(1), json!("first")]),
+                // Duplicate primary key.
+                (insert.clone(), vec![json!(1), json!("again")]),
+                (insert.clone(), vec![json!(3), json!("third")]),
+            ])
+            .await;
+            assert!(matches!(
+                result,
+                Err(DbError::StatementFailed { index: 1, .. })
+            ));
+            let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM zirv_test_atomic")
+                .fetch_one(pool)
+                .await
+                .unwrap();
+            assert_eq!(count, 0);
+
+            let affected = execute_all_atomic(&[
+                (insert.clone(), vec![json!(1), json!("first")]),
+                (insert, vec![json!(2), json!("second")]),
+                (
+                    "UPDATE zirv_test_atomic SET name = 'renamed'".to_owned(),
+                    Vec::new(),
+                ),
+            ])
+            .await
+            .unwrap();
+            assert_eq!(affected, [1, 1, 2]);
+
+            sqlx::query("DROP TABLE zirv_test_atomic")
+                .execute(pool)
+                .await
+                .unwrap();
+        });
+    }
+
+    #[test]
+    fn test_for_update_sql_appends_locking_clause() {
+        assert_eq!(
+            for_update_sql("SELECT id FROM jobs", false),
+            "SELECT id FROM jobs FOR UPDATE"
+        );
+        assert_eq!(
+            for_update_sql("SELECT id FROM jobs LIMIT 5; \n", true),
+            "SELECT id FROM jobs LIMIT 5 FOR UPDATE SKIP LOCKED"
+        );
+    }
+
+    #[test]
+    fn test_skip_locked_claims_disjoint_rows() {
+        test_util::run_with_db(|pool| async move {
+            sqlx::query("DROP TABLE IF EXISTS zirv_test_claim")
+                .execute(pool)
+                .await
+                .unwrap();
+            sqlx::query("CREATE TABLE zirv_test_claim (id INT PRIMARY KEY) ENGINE = InnoDB")
+                .execute(pool)
+                .await
+                .unwrap();
+            sqlx::query("INSERT INTO zirv_test_claim (id) VALUES (1), (2), (3), (4), (5)")
+                .execute(pool)
+                .await
+                .unwrap();
+            let claim = "SELECT id FROM zirv_test_claim ORDER BY id LIMIT 2";
+
+            let mut first = pool.begin().await.unwrap();
+            let mut second = pool.begin().await.unwrap();
+            let claimed_first: Vec<(i32,)> =
+                fetch_for_update(&mut first, claim, true).await.unwrap();
+            // The first transaction still holds its locks, so these rows are skipped.
+            let claimed_second: Vec<(i32,)> =
+                fetch_for_update(&mut second, claim, true).await.unwrap();
+            assert_eq!(claimed_first, [(1,), (2,)]);
+            assert_eq!(claimed_second, [(3,), (4,)]);
+
+            second.rollback().await.unwrap();
+            first.rollback().await.unwrap();
+            sqlx::query("DROP TABLE zirv_test_claim")
+                .execute(pool)
+                .await
+                .unwrap();
+        });
+    }
+
+    #[test]
+    fn test_watchdog_reports_and_kills_slow_transactions() {
+        // The report itself is covered by the watchdog's own tests; the shared test runtime
+        // runs the watchdog task on another thread, out of reach of a log capture.
+        test_util::run_with_db(|_| async {
+            let watchdog = WatchdogConfig {
+                limit: Some(Duration::from_millis(200)),
+                kill: true,
+            };
+
+            let started = Instant::now();
+            let interrupted: i64 = run_transaction(watchdog, async |tx| {
+                sqlx::query_scalar("SELECT SLEEP(5)")
+                    .fetch_one(&mut **tx)
+                    .await
+            })
+            .await
+            .unwrap();
+
+            // A killed SLEEP returns 1 instead of failing.
+            assert_eq!(interrupted, 1);
+            assert!(started.elapsed() < Duration::from_secs(4));
+        });
+    }
+}
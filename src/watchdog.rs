@@ -0,0 +1,153 @@
+use std::future::Future;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use sqlx::MySqlConnection;
+use tokio::task::JoinHandle;
+use zirv_config::read_config;
+
+use crate::db::get_db_pool;
+use crate::logging::{correlation_id, log_error, with_correlation_id};
+use crate::redact::format_db_error;
+
+static WATCHDOG_CONFIG: OnceLock<WatchdogConfig> = OnceLock::new();
+
+/// Settings of the transaction watchdog.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) struct WatchdogConfig {
+    /// How long a transaction may run before it is reported
+    /// (`database.max_transaction_duration_secs`, unset or `0` disables the watchdog).
+    pub(crate) limit: Option<Duration>,
+    /// Whether to kill the running query of a transaction that exceeds the limit
+    /// (`database.kill_long_transactions`, default `false`).
+    pub(crate) kill: bool,
+}
+
+impl WatchdogConfig {
+    /// Returns the settings from the `database` configuration, read once.
+    pub(crate) fn global() -> Self {
+        *WATCHDOG_CONFIG.get_or_init(|| WatchdogConfig {
+            limit: read_config!("database.max_transaction_duration_secs", u64)
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs),
+            kill: read_config!("database.kill_long_transactions", bool).unwrap_or(false),
+        })
+    }
+}
+
+/// A timer that runs an action unless it is dropped first.
+pub(crate) struct Watchdog(JoinHandle<()>);
+
+impl Watchdog {
+    /// Runs `action` after `limit`, keeping the current correlation id for its logs.
+    pub(crate) fn spawn(
+        limit: Duration,
+        action: impl Future<Output = ()> + Send + 'static,
+    ) -> Self {
+        let id = correlation_id();
+        Watchdog(tokio::spawn(async move {
+            tokio::time::sleep(limit).await;
+            match id {
+                Some(id) => with_correlation_id(id, action).await,
+                None => action.await,
+            }
+        }))
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Starts watching the transaction running on `conn`, if a limit is configured.
+///
+/// The transaction is watched until the returned watchdog is dropped.
+pub(crate) async fn watch_transaction(
+    conn: &mut MySqlConnection,
+    config: WatchdogConfig,
+) -> Result<Option<Watchdog>, sqlx::Error> {
+    let Some(limit) = config.limit else {
+        return Ok(None);
+    };
+    let connection_id: u64 = sqlx::query_scalar("SELECT CONNECTION_ID()")
+        .fetch_one(conn)
+        .await?;
+    Ok(Some(Watchdog::spawn(
+        limit,
+        on_expired(connection_id, limit, config.kill),
+    )))
+}
+
+/// Reports a transaction that exceeded its limit and, in kill mode, kills its running query.
+async fn on_expired(connection_id: u64, limit: Duration, kill: bool) {
+    log_error(format_args!(
+        "Transaction on connection {} has been running for more than {:?}",
+        connection_id, limit
+    ));
+    if !kill {
+        return;
+    }
+
+    log_error(format_args!(
+        "Killing the running query of connection {}",
+        connection_id
+    ));
+    let sql = format!("KILL QUERY {}", connection_id);
+    if let Err(e) = sqlx::raw_sql(&sql).execute(get_db_pool()).await {
+        log_error(format_args!(
+            "Failed to kill the query of connection {}: {}",
+            connection_id,
+            format_db_error(&e)
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    #[tokio::test]
+    async fn test_watchdog_fires_after_the_limit() {
+        let fired = Arc::new(AtomicBool::new(false));
+        let flag = fired.clone();
+        let _watchdog = Watchdog::spawn(Duration::from_millis(10), async move {
+            flag.store(true, Ordering::SeqCst);
+        });
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(fired.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_dropped_watchdog_does_not_fire() {
+        let fired = Arc::new(AtomicBool::new(false));
+        let flag = fired.clone();
+        let watchdog = Watchdog::spawn(Duration::from_millis(50), async move {
+            flag.store(true, Ordering::SeqCst);
+        });
+
+        drop(watchdog);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!fired.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_expired_transaction_is_logged() {
+        let (logs, _guard) = test_util::start_log_capture();
+        let _watchdog = Watchdog::spawn(
+            Duration::from_millis(10),
+            on_expired(42, Duration::from_millis(10), false),
+        );
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        assert_eq!(
+            logs.contents(),
+            "ERROR Transaction on connection 42 has been running for more than 10ms\n"
+        );
+    }
+}